@@ -0,0 +1,153 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// 符号链接最多跟几层，和 Linux 的 MAXSYMLINKS 一样
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// 先写同目录下的临时文件再 rename 过去，中途崩溃或者磁盘写满都不会留下写了一半的文件
+///
+/// 原文件是符号链接时写到链接指向的文件，链接本身不动；权限和属主照抄原文件
+pub async fn write(path: &Path, bytes: Vec<u8>) -> Result<()> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut pending = Pending::create_blocking(&path)?;
+        pending.tmp.write_all(&bytes)?;
+        pending.commit_blocking()
+    })
+    .await?
+}
+
+/// 还没替换原文件的临时文件，边收边写的大文件用；没 commit 就丢掉的话临时文件会被删掉
+pub struct Pending {
+    tmp: tempfile::NamedTempFile,
+    target: PathBuf,
+    original: Option<fs::Metadata>,
+}
+
+impl Pending {
+    pub async fn create(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || Self::create_blocking(&path)).await?
+    }
+
+    fn create_blocking(path: &Path) -> Result<Self> {
+        let target = resolve_symlinks(path)?;
+        let dir = parent(&target);
+        let file_name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let original = match fs::metadata(&target) {
+            Ok(meta) => Some(meta),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to stat {}", target.display()));
+            }
+        };
+
+        // rename 能绕过只读属性，得自己拦下来，和直接写文件时的行为保持一致
+        if original
+            .as_ref()
+            .is_some_and(|meta| meta.permissions().readonly())
+        {
+            anyhow::bail!("{} is read-only", target.display());
+        }
+
+        let prefix = format!(".{}.", file_name);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        // tempfile 默认 0600，新文件要和普通创建一样受 umask 管
+        #[cfg(unix)]
+        if original.is_none() {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o666));
+        }
+
+        let tmp = builder
+            .tempfile_in(dir)
+            .with_context(|| format!("Failed to create temporary file in {}", dir.display()))?;
+        Ok(Pending {
+            tmp,
+            target,
+            original,
+        })
+    }
+
+    /// 接着往临时文件后面写
+    pub async fn write(self, bytes: Vec<u8>) -> Result<Self> {
+        tokio::task::spawn_blocking(move || {
+            let mut pending = self;
+            pending.tmp.write_all(&bytes)?;
+            Ok(pending)
+        })
+        .await?
+    }
+
+    /// 落盘以后替换原文件
+    pub async fn commit(self) -> Result<()> {
+        tokio::task::spawn_blocking(move || self.commit_blocking()).await?
+    }
+
+    fn commit_blocking(self) -> Result<()> {
+        let Pending {
+            tmp,
+            target,
+            original,
+        } = self;
+        tmp.as_file().sync_all()?;
+
+        #[cfg(unix)]
+        if let Some(meta) = &original {
+            use std::os::unix::fs::MetadataExt;
+
+            fs::set_permissions(tmp.path(), meta.permissions())?;
+            // 不是 root 的话改不了别人的属主，这种情况原文件本来就是自己的，忽略就好
+            let _ = std::os::unix::fs::fchown(tmp.as_file(), Some(meta.uid()), Some(meta.gid()));
+        }
+        #[cfg(not(unix))]
+        let _ = original;
+
+        tmp.persist(&target)
+            .map_err(|e| e.error)
+            .with_context(|| format!("Failed to replace {}", target.display()))?;
+
+        // rename 本身也要落盘
+        #[cfg(unix)]
+        fs::File::open(parent(&target))?.sync_all()?;
+
+        Ok(())
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// 沿着符号链接一路找到真正的文件，最后一层不存在也没关系（新建文件）
+fn resolve_symlinks(path: &Path) -> Result<PathBuf> {
+    let mut current = path.to_path_buf();
+
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let link = fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(parent) if link.is_relative() => parent.join(link),
+                    _ => link,
+                };
+            }
+            _ => return Ok(current),
+        }
+    }
+
+    anyhow::bail!("Too many levels of symbolic links: {}", path.display())
+}
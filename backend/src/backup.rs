@@ -0,0 +1,157 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime};
+use clap::ValueEnum;
+
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum BackupMode {
+    /// 不备份
+    #[value(name = "none")]
+    None,
+    /// 上一个版本存成同目录下的 `file.txt~`
+    #[value(name = "tilde")]
+    Tilde,
+    /// 带时间戳存进备份目录，按数量和时间轮换
+    #[value(name = "dir")]
+    Dir,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupPolicy {
+    pub mode: BackupMode,
+    /// 相对路径相对于文件所在目录
+    pub dir: PathBuf,
+    /// 每个文件最多留几份，0 表示不限
+    pub keep: usize,
+    pub max_age: Option<Duration>,
+}
+
+/// 保存前把磁盘上的旧版本复制一份，文件还不存在就什么都不做
+pub async fn backup(path: &Path, policy: &BackupPolicy) -> Result<Option<PathBuf>> {
+    if policy.mode == BackupMode::None || !tokio::fs::try_exists(path).await? {
+        return Ok(None);
+    }
+
+    let Some(file_name) = path.file_name() else {
+        return Ok(None);
+    };
+
+    let backup_path = match policy.mode {
+        BackupMode::None => return Ok(None),
+        BackupMode::Tilde => {
+            let mut name = file_name.to_os_string();
+            name.push("~");
+            path.with_file_name(name)
+        }
+        BackupMode::Dir => {
+            let dir = backup_dir(path, policy);
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .with_context(|| format!("Failed to create backup directory {}", dir.display()))?;
+
+            // 连续保存没改过的内容就别再多存一份了
+            let existing = list_backups(&dir, file_name).await?;
+            if let Some((newest, _)) = existing.last()
+                && same_content(path, newest).await?
+            {
+                return Ok(None);
+            }
+
+            let mut name = file_name.to_os_string();
+            name.push(".");
+            name.push(Local::now().format(STAMP_FORMAT).to_string());
+            dir.join(name)
+        }
+    };
+
+    tokio::fs::copy(path, &backup_path)
+        .await
+        .with_context(|| format!("Failed to back up to {}", backup_path.display()))?;
+
+    if policy.mode == BackupMode::Dir {
+        rotate(&backup_dir(path, policy), file_name, policy).await?;
+    }
+
+    Ok(Some(backup_path))
+}
+
+fn backup_dir(path: &Path, policy: &BackupPolicy) -> PathBuf {
+    match path.parent() {
+        Some(parent) => parent.join(&policy.dir),
+        None => policy.dir.clone(),
+    }
+}
+
+/// 某个文件的所有备份和备份时间，按时间从旧到新
+async fn list_backups(dir: &Path, file_name: &OsStr) -> Result<Vec<(PathBuf, NaiveDateTime)>> {
+    let mut prefix = file_name.to_os_string();
+    prefix.push(".");
+    let prefix = prefix.to_string_lossy().to_string();
+
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        // 时间戳在文件名里，不看 mtime：Windows 上复制文件会把原文件的 mtime 也带过来
+        if let Some(stamp) = name.to_string_lossy().strip_prefix(&prefix)
+            && let Ok(time) = NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT)
+        {
+            backups.push((entry.path(), time));
+        }
+    }
+
+    backups.sort_by_key(|(_, time)| *time);
+    Ok(backups)
+}
+
+async fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    if tokio::fs::metadata(a).await?.len() != tokio::fs::metadata(b).await?.len() {
+        return Ok(false);
+    }
+    Ok(tokio::fs::read(a).await? == tokio::fs::read(b).await?)
+}
+
+async fn rotate(dir: &Path, file_name: &OsStr, policy: &BackupPolicy) -> Result<()> {
+    let backups = list_backups(dir, file_name).await?;
+    let excess = match policy.keep {
+        0 => 0,
+        keep => backups.len().saturating_sub(keep),
+    };
+    let now = Local::now().naive_local();
+
+    for (i, (backup, time)) in backups.iter().enumerate() {
+        let expired = policy
+            .max_age
+            .is_some_and(|max_age| (now - *time).to_std().is_ok_and(|age| age > max_age));
+
+        // 刚存的那份永远留着
+        if (i < excess || expired) && i + 1 < backups.len() {
+            tokio::fs::remove_file(backup).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 天数太大就当成永远不过期，别溢出
+pub fn days(days: u64) -> Duration {
+    Duration::from_secs(days.saturating_mul(24 * 60 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_saturate() {
+        assert_eq!(days(2), Duration::from_secs(2 * 86400));
+        assert_eq!(days(u64::MAX), Duration::from_secs(u64::MAX));
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+/// 目录里的一项，前端自己画选择框用
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub dir: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Listing {
+    /// 相对于根目录，用 `/` 分隔，根目录是空字符串
+    pub path: String,
+    /// 已经在根目录了就没有
+    pub parent: Option<String>,
+    /// 目录在前，同类按名字排
+    pub entries: Vec<Entry>,
+}
+
+/// 路径跳出了根目录
+#[derive(Debug)]
+pub struct OutsideRoot(pub PathBuf);
+
+impl fmt::Display for OutsideRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is outside the allowed directory", self.0.display())
+    }
+}
+
+impl std::error::Error for OutsideRoot {}
+
+/// 把前端传来的路径解析到根目录下，出不去
+///
+/// 相对路径相对于根目录；`..` 不能越过根目录，符号链接指到外面也不行。
+/// `root` 要先 canonicalize 过
+pub async fn resolve(root: &Path, path: &str) -> Result<PathBuf> {
+    let path = Path::new(path);
+    let mut resolved = root.to_path_buf();
+
+    if path.is_absolute() {
+        // 绝对路径也行，只要在根目录里面
+        resolved = PathBuf::new();
+    }
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => resolved.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    bail!(OutsideRoot(path.to_path_buf()));
+                }
+            }
+            Component::Normal(name) => resolved.push(name),
+        }
+    }
+
+    if !resolved.starts_with(root) {
+        bail!(OutsideRoot(path.to_path_buf()));
+    }
+
+    // 存在的那一截可能有符号链接，实际位置也得在根目录里面
+    let mut existing = resolved.as_path();
+    loop {
+        match tokio::fs::canonicalize(existing).await {
+            Ok(real) => {
+                if !real.starts_with(root) {
+                    bail!(OutsideRoot(path.to_path_buf()));
+                }
+                break;
+            }
+            Err(_) => match existing.parent() {
+                Some(parent) => existing = parent,
+                None => break,
+            },
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// 给前端看的相对路径
+pub fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 列出根目录下某个目录的内容，隐藏文件不列
+pub async fn list(root: &Path, path: &str) -> Result<Listing> {
+    let dir = resolve(root, path).await?;
+
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(&dir)
+        .await
+        .with_context(|| format!("Failed to list {}", dir.display()))?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        // 符号链接指到根目录外面的就别列出来了，反正也进不去
+        let Ok(real) = tokio::fs::canonicalize(entry.path()).await else {
+            continue;
+        };
+        if !real.starts_with(root) {
+            continue;
+        }
+        let dir = tokio::fs::metadata(&real).await.is_ok_and(|m| m.is_dir());
+        entries.push(Entry { name, dir });
+    }
+    entries.sort_by(|a, b| b.dir.cmp(&a.dir).then_with(|| a.name.cmp(&b.name)));
+
+    let parent = (dir != root)
+        .then(|| dir.parent().map(|p| relative(root, p)))
+        .flatten();
+
+    Ok(Listing {
+        path: relative(root, &dir),
+        parent,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::create_dir(root.join("drafts")).unwrap();
+        (dir, root)
+    }
+
+    fn outside(result: Result<PathBuf>) -> bool {
+        result.is_err_and(|e| e.is::<OutsideRoot>())
+    }
+
+    #[tokio::test]
+    async fn relative_paths_stay_under_root() {
+        let (_dir, root) = root();
+        assert_eq!(resolve(&root, "").await.unwrap(), root);
+        assert_eq!(
+            resolve(&root, "drafts/./new.txt").await.unwrap(),
+            root.join("drafts").join("new.txt")
+        );
+        assert_eq!(
+            resolve(&root, "drafts/../a.txt").await.unwrap(),
+            root.join("a.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn parent_dir_cannot_leave_root() {
+        let (_dir, root) = root();
+        assert!(outside(resolve(&root, "..").await));
+        assert!(outside(resolve(&root, "../x.txt").await));
+        assert!(outside(resolve(&root, "drafts/../../x.txt").await));
+        let name = root.file_name().unwrap().to_string_lossy();
+        // 绕出去再绕回根目录里面没关系，只看最后落在哪
+        assert!(resolve(&root, &format!("../{}/a.txt", name)).await.is_ok());
+        assert!(outside(
+            resolve(&root, &format!("../{}-other/a.txt", name)).await
+        ));
+    }
+
+    #[tokio::test]
+    async fn absolute_paths_must_be_inside_root() {
+        let (_dir, root) = root();
+        let inside = root.join("drafts").join("a.txt");
+        assert_eq!(
+            resolve(&root, &inside.to_string_lossy()).await.unwrap(),
+            inside
+        );
+
+        let parent = root.parent().unwrap().join("a.txt");
+        assert!(outside(resolve(&root, &parent.to_string_lossy()).await));
+        // 前缀相同的兄弟目录不算在里面
+        let sibling = format!("{}-other/a.txt", root.display());
+        assert!(outside(resolve(&root, &sibling).await));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_cannot_escape_root() {
+        let (_dir, root) = root();
+        let elsewhere = tempfile::tempdir().unwrap();
+        std::fs::write(elsewhere.path().join("secret.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(elsewhere.path(), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(
+            elsewhere.path().join("secret.txt"),
+            root.join("file-link.txt"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(root.join("drafts"), root.join("inner")).unwrap();
+
+        assert!(outside(resolve(&root, "link").await));
+        assert!(outside(resolve(&root, "link/secret.txt").await));
+        // 还不存在的文件，看存在的那一截
+        assert!(outside(resolve(&root, "link/new/deeper.txt").await));
+        assert!(outside(resolve(&root, "file-link.txt").await));
+        // 指向根目录里面的链接照常能用
+        assert!(resolve(&root, "inner/new.txt").await.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listing_hides_escaping_links() {
+        let (_dir, root) = root();
+        let elsewhere = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(elsewhere.path(), root.join("link")).unwrap();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+
+        let listing = list(&root, "").await.unwrap();
+        let names: Vec<_> = listing.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["drafts", "a.txt"]);
+        assert_eq!(listing.parent, None);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::Command;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+const FILE_NAME: &str = "config.toml";
+
+/// `[editor]` 里的编辑器偏好，交给前端
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all(deserialize = "kebab-case"), deny_unknown_fields)]
+pub struct Editor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+    /// 像素
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_height: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tab_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spellcheck: Option<bool>,
+    /// 打开时的缩放，1 是 100%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// 读到了的配置文件，按优先级从低到高
+    pub files: Vec<PathBuf>,
+    /// 顶层的键，和命令行的长参数同名
+    options: Table,
+    pub editor: Editor,
+}
+
+fn global_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("simply-writer").join(FILE_NAME))
+}
+
+/// 从 `dir` 往上找最近的目录配置
+fn local_path(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(".simply-writer").join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+fn read(path: &Path) -> Result<Table> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    text.parse::<Table>()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// 文件关联打开时传不了参数，常用的设置写在配置文件里
+///
+/// 全局的在 `~/.config/simply-writer/config.toml`（Windows 上在 `%APPDATA%`），
+/// `document` 所在目录（没有文档就是当前目录）往上最近的 `.simply-writer/config.toml` 覆盖全局的
+pub fn load(document: Option<&str>) -> Result<Config> {
+    let dir = match document {
+        Some(path) => std::path::absolute(path)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => std::env::current_dir()?,
+    };
+
+    let mut config = Config::default();
+    let mut editor = Table::new();
+    for path in [global_path(), local_path(&dir)].into_iter().flatten() {
+        if !path.is_file() {
+            continue;
+        }
+
+        let mut table = read(&path)?;
+        // [editor] 按键合并，目录里的只写想改的那几项就行
+        if let Some(section) = table.remove("editor") {
+            let Value::Table(section) = section else {
+                bail!("[editor] in {} must be a table", path.display());
+            };
+            editor.extend(section);
+        }
+        config.options.extend(table);
+        config.files.push(path);
+    }
+
+    config.editor =
+        Editor::deserialize(Value::Table(editor)).context("Invalid [editor] settings")?;
+    Ok(config)
+}
+
+impl Config {
+    /// 顶层某一项的值，按命令行参数的写法解析；运行中换了文档要重新读的设置用这个
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = self.options.get(key) else {
+            return Ok(None);
+        };
+        let value = scalar(value)
+            .with_context(|| format!("Unsupported value for `{}` in the config file", key))?;
+        match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => bail!("Invalid value for `{}` in the config file: {}", key, e),
+        }
+    }
+
+    /// 配置文件里的值当成命令行参数的默认值，命令行和环境变量照样能盖过它
+    pub fn apply(&self, mut command: Command) -> Result<Command> {
+        for (key, value) in &self.options {
+            let Some(id) = command
+                .get_arguments()
+                .find(|arg| {
+                    arg.get_long() == Some(key) && !matches!(key.as_str(), "help" | "version")
+                })
+                .map(|arg| arg.get_id().clone())
+            else {
+                bail!("Unknown option `{}` in the config file", key);
+            };
+
+            let values = match value {
+                Value::Array(items) => items.iter().map(scalar).collect::<Option<Vec<_>>>(),
+                value => scalar(value).map(|value| vec![value]),
+            }
+            .with_context(|| format!("Unsupported value for `{}` in the config file", key))?;
+
+            // 密码之类不该出现在 --help 里
+            command = command.mut_arg(id, |arg| {
+                let hide = arg.is_hide_env_values_set();
+                arg.default_values(values).hide_default_value(hide)
+            });
+        }
+        Ok(command)
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> Config {
+        Config {
+            options: text.parse().unwrap(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn get_parses_like_the_command_line() {
+        let config = config("target = 5000\ndaily-goal = \"300\"");
+        assert_eq!(config.get::<usize>("target").unwrap(), Some(5000));
+        assert_eq!(config.get::<usize>("daily-goal").unwrap(), Some(300));
+        assert_eq!(config.get::<usize>("port").unwrap(), None);
+    }
+
+    #[test]
+    fn get_rejects_bad_values() {
+        let config = config("target = \"lots\"\nhost = [1, 2]");
+        assert!(config.get::<usize>("target").is_err());
+        assert!(config.get::<String>("host").is_err());
+    }
+}
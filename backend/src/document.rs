@@ -0,0 +1,138 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use encoding_rs::Encoding;
+
+use crate::{
+    atomic_write,
+    encoding::{self, Encodes},
+    line_ending::{self, LineEnding},
+    revision,
+};
+
+/// 文件在字节层面的格式，保存时原样还原，免得 diff 一片红
+#[derive(Debug, Clone)]
+pub struct FileFormat {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    pub line_ending: LineEnding,
+    // 换行符混用时原文件里每一处换行
+    pub line_breaks: Arc<[LineEnding]>,
+}
+
+impl FileFormat {
+    pub fn new(encoding: &'static Encoding) -> Self {
+        FileFormat {
+            encoding,
+            bom: false,
+            line_ending: LineEnding::default(),
+            line_breaks: Arc::new([]),
+        }
+    }
+}
+
+/// 当前打开的文件实际用的格式和状态，重新加载和保存时沿用
+#[derive(Debug, Clone)]
+pub struct OpenedFile {
+    pub format: FileFormat,
+    pub read_only: bool,
+    pub lossy: bool,
+    // 只读时拒绝保存要把非法字节的位置带上
+    pub decode_errors: Vec<usize>,
+    // 最近一次读 / 写时的版本号，用来分辨文件变化是不是自己保存引起的
+    pub revision: Option<String>,
+    // 这个版本的内容（换行统一成 \n），PATCH 就在它上面改
+    pub content: Arc<str>,
+}
+
+impl OpenedFile {
+    /// 还没读过的文件（新建的、路径上还没有文件的）
+    pub fn new(format: FileFormat) -> Self {
+        OpenedFile {
+            format,
+            read_only: false,
+            lossy: false,
+            decode_errors: Vec::new(),
+            revision: None,
+            content: Arc::from(""),
+        }
+    }
+}
+
+/// 正在编辑的文档，运行中可以换成别的文件或者新建一个
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    /// 还没保存过的新文档没有路径
+    pub path: Option<String>,
+    /// 读过或者写过之后才有
+    pub opened: Option<OpenedFile>,
+}
+
+impl Document {
+    pub fn title(&self) -> String {
+        match &self.path {
+            Some(path) => title_of(path),
+            None => crate::DEFAULT_FILE_NAME.to_string(),
+        }
+    }
+}
+
+pub fn title_of(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// 读出来的文件
+pub struct Loaded {
+    pub content: String,
+    pub format: FileFormat,
+    pub malformed: Vec<usize>,
+    pub revision: String,
+}
+
+pub async fn read_with_encoding(path: &str, encoding: &Encodes) -> Result<Loaded> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path))?;
+    let revision = revision::compute(&bytes, &tokio::fs::metadata(path).await?);
+
+    let encoder = match encoding {
+        Encodes::Auto => encoding::detect(&bytes),
+        Encodes::Fixed(encoding) => encoding,
+    };
+
+    // 解码错误不能丢，编码对不上时按下保存就会用 U+FFFD 顶掉原本的信息
+    let decoded = encoding::decode(&bytes, encoder);
+    let (line_ending, line_breaks) = line_ending::detect(&decoded.text);
+    let content = line_ending::normalize(&decoded.text).into_owned();
+
+    let format = FileFormat {
+        encoding: decoded.encoding,
+        bom: decoded.bom,
+        line_ending,
+        line_breaks: line_breaks.into(),
+    };
+
+    Ok(Loaded {
+        content,
+        format,
+        malformed: decoded.malformed,
+        revision,
+    })
+}
+
+/// 返回写完之后的版本号
+pub async fn write_with_encoding(path: &str, content: &str, format: &FileFormat) -> Result<String> {
+    let content = line_ending::restore(content, format.line_ending, &format.line_breaks);
+    let encoded_bytes = encoding::encode(&content, format.encoding, format.bom)?;
+    let digest = revision::digest(&encoded_bytes);
+
+    atomic_write::write(Path::new(path), encoded_bytes).await?;
+
+    Ok(revision::with_digest(
+        &digest,
+        &tokio::fs::metadata(path).await?,
+    ))
+}
@@ -0,0 +1,634 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+pub const CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+/// 无序列表都用这个编号，有序列表每个单独一个，才能各自从头编号
+const BULLET_NUM: usize = 1;
+const BULLET_ABSTRACT: usize = 0;
+const DECIMAL_ABSTRACT: usize = 1;
+
+/// 把文档转成 .docx；`markdown` 时标题、强调、列表等换成 Word 的样式，不然按空行分段
+pub fn build(title: &str, content: &str, markdown: bool) -> Result<Vec<u8>> {
+    let mut body = Body::default();
+    if markdown {
+        body.markdown(content);
+    } else {
+        body.plain(content);
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let parts = [
+        ("[Content_Types].xml", CONTENT_TYPES.to_string()),
+        ("_rels/.rels", ROOT_RELS.to_string()),
+        ("docProps/core.xml", core_properties(title)),
+        ("word/_rels/document.xml.rels", DOCUMENT_RELS.to_string()),
+        ("word/document.xml", body.document()),
+        ("word/styles.xml", STYLES.to_string()),
+        ("word/numbering.xml", body.numbering()),
+    ];
+    for (name, xml) in parts {
+        zip.start_file(name, options)?;
+        zip.write_all(xml.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[derive(Default)]
+struct Body {
+    xml: String,
+    /// 有序列表的起始编号和所在的层级，按 w:num 的顺序（从 2 开始，1 是无序列表）
+    ordered: Vec<(u64, usize)>,
+}
+
+/// 正在拼的段落
+#[derive(Default)]
+struct Paragraph {
+    style: Option<&'static str>,
+    /// (w:numId, w:ilvl)
+    numbering: Option<(usize, usize)>,
+    runs: String,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+}
+
+impl Body {
+    /// 空行分段，段落里单独的换行保留成软回车
+    fn plain(&mut self, content: &str) {
+        for block in blocks(content) {
+            let mut paragraph = Paragraph::default();
+            for (i, line) in block.split('\n').enumerate() {
+                if i > 0 {
+                    paragraph.runs.push_str("<w:r><w:br/></w:r>");
+                }
+                paragraph.text(line, Format::default());
+            }
+            self.push(paragraph);
+        }
+    }
+
+    fn markdown(&mut self, content: &str) {
+        let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        let mut current: Option<Paragraph> = None;
+        let mut format = Format::default();
+        // 每一层列表的 numId
+        let mut lists: Vec<usize> = Vec::new();
+        let mut quote = 0;
+        let mut heading: Option<&'static str> = None;
+        let mut code_block = false;
+        // 软换行先记着，看前后是不是中文再决定要不要补空格
+        let mut soft_break = false;
+
+        for event in Parser::new_ext(content, options) {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    self.flush(&mut current);
+                    let style = heading_style(level);
+                    heading = Some(style);
+                    current = Some(Paragraph::styled(style));
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    heading = None;
+                    self.flush(&mut current);
+                }
+                Event::Start(Tag::Paragraph) => {
+                    // 列表项刚开头的段落就是列表项那一段
+                    if current.as_ref().is_some_and(|p| p.runs.is_empty()) {
+                        continue;
+                    }
+                    self.flush(&mut current);
+                    current = Some(match (lists.is_empty(), quote) {
+                        (false, _) => Paragraph::styled("ListParagraph"),
+                        (true, 0) => Paragraph::default(),
+                        (true, _) => Paragraph::styled("Quote"),
+                    });
+                }
+                Event::End(TagEnd::Paragraph) => self.flush(&mut current),
+                Event::Start(Tag::BlockQuote(_)) => {
+                    self.flush(&mut current);
+                    quote += 1;
+                }
+                Event::End(TagEnd::BlockQuote(_)) => quote -= 1,
+                Event::Start(Tag::CodeBlock(_)) => {
+                    self.flush(&mut current);
+                    code_block = true;
+                }
+                Event::End(TagEnd::CodeBlock) => code_block = false,
+                Event::Start(Tag::List(start)) => {
+                    self.flush(&mut current);
+                    lists.push(match start {
+                        Some(start) => {
+                            self.ordered.push((start, lists.len()));
+                            BULLET_NUM + self.ordered.len()
+                        }
+                        None => BULLET_NUM,
+                    });
+                }
+                Event::End(TagEnd::List(_)) => {
+                    self.flush(&mut current);
+                    lists.pop();
+                }
+                Event::Start(Tag::Item) => {
+                    self.flush(&mut current);
+                    let mut paragraph = Paragraph::styled("ListParagraph");
+                    paragraph.numbering = lists.last().map(|&num| (num, lists.len() - 1));
+                    current = Some(paragraph);
+                }
+                Event::End(TagEnd::Item) => self.flush(&mut current),
+                Event::Start(Tag::Emphasis) => format.italic = true,
+                Event::End(TagEnd::Emphasis) => format.italic = false,
+                Event::Start(Tag::Strong) => format.bold = true,
+                Event::End(TagEnd::Strong) => format.bold = false,
+                Event::Start(Tag::Strikethrough) => format.strike = true,
+                Event::End(TagEnd::Strikethrough) => format.strike = false,
+                Event::Text(text) if code_block => {
+                    // 代码块每一行一段，空白原样保留
+                    for line in text.strip_suffix('\n').unwrap_or(&text).split('\n') {
+                        let mut paragraph = Paragraph::styled("CodeBlock");
+                        paragraph.text(line, Format::default());
+                        self.push(paragraph);
+                    }
+                }
+                // Word 里显示不了 HTML，行内的就当普通文字
+                Event::Text(text) | Event::InlineMath(text) | Event::InlineHtml(text) => {
+                    let paragraph = current.get_or_insert_with(|| Paragraph::open(heading));
+                    paragraph.inline(&text, format, std::mem::take(&mut soft_break));
+                }
+                Event::Code(text) => {
+                    let paragraph = current.get_or_insert_with(|| Paragraph::open(heading));
+                    let code = Format {
+                        code: true,
+                        ..format
+                    };
+                    paragraph.inline(&text, code, std::mem::take(&mut soft_break));
+                }
+                Event::SoftBreak => soft_break = true,
+                Event::HardBreak => {
+                    if let Some(paragraph) = &mut current {
+                        paragraph.runs.push_str("<w:r><w:br/></w:r>");
+                    }
+                }
+                Event::Rule => {
+                    self.flush(&mut current);
+                    self.push(Paragraph::styled("Rule"));
+                }
+                Event::TaskListMarker(done) => {
+                    if let Some(paragraph) = &mut current {
+                        paragraph.text(if done { "☑ " } else { "☐ " }, format);
+                    }
+                }
+                // 链接留下文字，图片留下替代文字，HTML 块和其它的不要
+                _ => {}
+            }
+        }
+        self.flush(&mut current);
+    }
+
+    fn flush(&mut self, current: &mut Option<Paragraph>) {
+        if let Some(paragraph) = current.take() {
+            self.push(paragraph);
+        }
+    }
+
+    fn push(&mut self, paragraph: Paragraph) {
+        self.xml.push_str("<w:p>");
+        if paragraph.style.is_some() || paragraph.numbering.is_some() {
+            self.xml.push_str("<w:pPr>");
+            if let Some(style) = paragraph.style {
+                self.xml
+                    .push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+            }
+            if let Some((num, level)) = paragraph.numbering {
+                self.xml.push_str(&format!(
+                    "<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                    level.min(8),
+                    num
+                ));
+            }
+            self.xml.push_str("</w:pPr>");
+        }
+        self.xml.push_str(&paragraph.runs);
+        self.xml.push_str("</w:p>");
+    }
+
+    fn document(&self) -> String {
+        format!(
+            "{}<w:document xmlns:w=\"{}\"><w:body>{}<w:sectPr>\
+             <w:pgSz w:w=\"11906\" w:h=\"16838\"/>\
+             <w:pgMar w:top=\"1440\" w:right=\"1800\" w:bottom=\"1440\" w:left=\"1800\" \
+             w:header=\"851\" w:footer=\"992\" w:gutter=\"0\"/>\
+             </w:sectPr></w:body></w:document>",
+            XML_HEADER, W_NS, self.xml
+        )
+    }
+
+    fn numbering(&self) -> String {
+        let levels = |abstract_id: usize| {
+            (0..9)
+                .map(|level| {
+                    let (format, text) = match abstract_id {
+                        BULLET_ABSTRACT => ("bullet", ["•", "◦", "▪"][level % 3].to_string()),
+                        _ => ("decimal", format!("%{}.", level + 1)),
+                    };
+                    format!(
+                        "<w:lvl w:ilvl=\"{level}\"><w:start w:val=\"1\"/>\
+                         <w:numFmt w:val=\"{format}\"/><w:lvlText w:val=\"{text}\"/>\
+                         <w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                        720 * (level + 1)
+                    )
+                })
+                .collect::<String>()
+        };
+
+        let mut xml = format!("{}<w:numbering xmlns:w=\"{}\">", XML_HEADER, W_NS);
+        for abstract_id in [BULLET_ABSTRACT, DECIMAL_ABSTRACT] {
+            xml.push_str(&format!(
+                "<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>{}</w:abstractNum>",
+                abstract_id,
+                levels(abstract_id)
+            ));
+        }
+        xml.push_str(&format!(
+            "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/></w:num>",
+            BULLET_NUM, BULLET_ABSTRACT
+        ));
+        // 每个有序列表在自己那一层从自己的起始编号开始，比如嵌套的 `3.` 接着上一段编号；
+        // 其它层级都从 1 开始
+        for (i, &(start, list_level)) in self.ordered.iter().enumerate() {
+            xml.push_str(&format!(
+                "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>",
+                BULLET_NUM + 1 + i,
+                DECIMAL_ABSTRACT
+            ));
+            for level in 0..9 {
+                let start = if level == list_level.min(8) { start } else { 1 };
+                xml.push_str(&format!(
+                    "<w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride>",
+                    level, start
+                ));
+            }
+            xml.push_str("</w:num>");
+        }
+        xml.push_str("</w:numbering>");
+        xml
+    }
+}
+
+impl Paragraph {
+    fn styled(style: &'static str) -> Self {
+        Paragraph {
+            style: Some(style),
+            ..Default::default()
+        }
+    }
+
+    /// 没有开着的段落时补一个，比如列表项里嵌套的列表后面又跟了文字
+    fn open(heading: Option<&'static str>) -> Self {
+        match heading {
+            Some(style) => Paragraph::styled(style),
+            None => Paragraph::default(),
+        }
+    }
+
+    /// 行内的文字；前面是软换行的话，两边都是中文就直接接上，不然补个空格
+    fn inline(&mut self, text: &str, format: Format, soft_break: bool) {
+        if soft_break && !(self.ends_wide() && text.starts_with(is_wide)) {
+            self.text(" ", format);
+        }
+        self.text(text, format);
+    }
+
+    fn text(&mut self, text: &str, format: Format) {
+        if text.is_empty() {
+            return;
+        }
+        self.runs.push_str("<w:r>");
+        if format.bold || format.italic || format.strike || format.code {
+            self.runs.push_str("<w:rPr>");
+            if format.code {
+                self.runs.push_str("<w:rStyle w:val=\"CodeChar\"/>");
+            }
+            if format.bold {
+                self.runs.push_str("<w:b/><w:bCs/>");
+            }
+            if format.italic {
+                self.runs.push_str("<w:i/><w:iCs/>");
+            }
+            if format.strike {
+                self.runs.push_str("<w:strike/>");
+            }
+            self.runs.push_str("</w:rPr>");
+        }
+        // Tab 要单独写成 w:tab
+        for (i, part) in text.split('\t').enumerate() {
+            if i > 0 {
+                self.runs.push_str("<w:tab/>");
+            }
+            if !part.is_empty() {
+                self.runs.push_str("<w:t xml:space=\"preserve\">");
+                self.runs.push_str(&escape(part));
+                self.runs.push_str("</w:t>");
+            }
+        }
+        self.runs.push_str("</w:r>");
+    }
+
+    /// 最后一个字是不是中日韩文字或全角标点
+    fn ends_wide(&self) -> bool {
+        self.runs
+            .strip_suffix("</w:t></w:r>")
+            .and_then(|runs| runs.chars().next_back())
+            .is_some_and(is_wide)
+    }
+}
+
+/// 中文换行不加空格，英文换行要加
+fn is_wide(c: char) -> bool {
+    c >= '\u{2E80}'
+}
+
+/// 按空行切成段落，段落前后的空行不算
+fn blocks(content: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+    for line in content.split('\n') {
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                blocks.push(block.join("\n"));
+                block.clear();
+            }
+        } else {
+            block.push(line);
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block.join("\n"));
+    }
+    blocks
+}
+
+fn heading_style(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "Heading1",
+        HeadingLevel::H2 => "Heading2",
+        HeadingLevel::H3 => "Heading3",
+        HeadingLevel::H4 => "Heading4",
+        HeadingLevel::H5 => "Heading5",
+        HeadingLevel::H6 => "Heading6",
+    }
+}
+
+/// XML 里不允许的控制字符直接丢掉，不然 Word 打不开
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\u{0}'..='\u{8}'
+            | '\u{B}'
+            | '\u{C}'
+            | '\u{E}'..='\u{1F}'
+            | '\u{FFFE}'
+            | '\u{FFFF}' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn core_properties(title: &str) -> String {
+    let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    format!(
+        "{}<cp:coreProperties \
+         xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\
+         <dc:title>{}</dc:title>\
+         <dcterms:created xsi:type=\"dcterms:W3CDTF\">{now}</dcterms:created>\
+         <dcterms:modified xsi:type=\"dcterms:W3CDTF\">{now}</dcterms:modified>\
+         </cp:coreProperties>",
+        XML_HEADER,
+        escape(title)
+    )
+}
+
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n";
+const W_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/><Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/></Relationships>"#;
+
+const DOCUMENT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering" Target="numbering.xml"/></Relationships>"#;
+
+/// 用 Word 内置样式的名字，对方改样式、生成目录都照常
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:docDefaults><w:rPrDefault><w:rPr><w:sz w:val="24"/><w:szCs w:val="24"/><w:lang w:val="en-US" w:eastAsia="zh-CN"/></w:rPr></w:rPrDefault><w:pPrDefault><w:pPr><w:spacing w:after="120" w:line="360" w:lineRule="auto"/></w:pPr></w:pPrDefault></w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:qFormat/></w:style><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="480" w:after="240"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:bCs/><w:sz w:val="44"/><w:szCs w:val="44"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="360" w:after="180"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:bCs/><w:sz w:val="36"/><w:szCs w:val="36"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="120"/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:bCs/><w:sz w:val="32"/><w:szCs w:val="32"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading4"><w:name w:val="heading 4"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:outlineLvl w:val="3"/></w:pPr><w:rPr><w:b/><w:bCs/><w:sz w:val="28"/><w:szCs w:val="28"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading5"><w:name w:val="heading 5"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:outlineLvl w:val="4"/></w:pPr><w:rPr><w:b/><w:bCs/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading6"><w:name w:val="heading 6"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:outlineLvl w:val="5"/></w:pPr><w:rPr><w:b/><w:bCs/><w:i/><w:iCs/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:ind w:left="720" w:right="720"/></w:pPr><w:rPr><w:i/><w:iCs/><w:color w:val="404040"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:ind w:left="720"/><w:contextualSpacing/></w:pPr></w:style><w:style w:type="paragraph" w:customStyle="1" w:styleId="CodeBlock"><w:name w:val="Code Block"/><w:basedOn w:val="Normal"/><w:pPr><w:shd w:val="clear" w:color="auto" w:fill="F2F2F2"/><w:spacing w:after="0" w:line="240" w:lineRule="auto"/></w:pPr><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas"/><w:sz w:val="20"/><w:szCs w:val="20"/></w:rPr></w:style><w:style w:type="paragraph" w:customStyle="1" w:styleId="Rule"><w:name w:val="Horizontal Rule"/><w:basedOn w:val="Normal"/><w:pPr><w:pBdr><w:bottom w:val="single" w:sz="6" w:space="1" w:color="auto"/></w:pBdr></w:pPr></w:style><w:style w:type="character" w:customStyle="1" w:styleId="CodeChar"><w:name w:val="Code Char"/><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas"/></w:rPr></w:style></w:styles>"#;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn parts(docx: &[u8]) -> Vec<(String, String)> {
+        let mut zip = zip::ZipArchive::new(Cursor::new(docx)).unwrap();
+        (0..zip.len())
+            .map(|i| {
+                let mut file = zip.by_index(i).unwrap();
+                let mut xml = String::new();
+                file.read_to_string(&mut xml).unwrap();
+                (file.name().to_string(), xml)
+            })
+            .collect()
+    }
+
+    fn part(docx: &[u8], name: &str) -> String {
+        parts(docx)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, xml)| xml)
+            .unwrap()
+    }
+
+    /// 标签成对、只有一个根元素、文字里没有裸的 `<` 和 `&`；够用了，不用为此引入 XML 解析库
+    fn assert_well_formed(name: &str, xml: &str) {
+        let rest = xml.strip_prefix(XML_HEADER).unwrap_or_else(|| {
+            panic!("{} has no XML declaration", name);
+        });
+        let mut stack: Vec<&str> = Vec::new();
+        let mut roots = 0;
+        let mut rest = rest;
+        while let Some(open) = rest.find('<') {
+            assert_text(name, &rest[..open], !stack.is_empty());
+            let close = open + rest[open..].find('>').unwrap();
+            let tag = &rest[open + 1..close];
+            assert!(!tag.contains('<'), "{}: broken tag {:?}", name, tag);
+            if let Some(closing) = tag.strip_prefix('/') {
+                assert_eq!(stack.pop(), Some(closing), "{}: unbalanced tags", name);
+            } else {
+                if stack.is_empty() {
+                    roots += 1;
+                }
+                assert_eq!(tag.matches('"').count() % 2, 0, "{}: {:?}", name, tag);
+                if !tag.ends_with('/') {
+                    stack.push(tag.split([' ', '/']).next().unwrap());
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+        assert!(stack.is_empty(), "{}: unclosed {:?}", name, stack);
+        assert!(rest.trim().is_empty(), "{}: text after the root", name);
+        assert_eq!(roots, 1, "{}", name);
+    }
+
+    fn assert_text(name: &str, text: &str, inside: bool) {
+        assert!(
+            inside || text.trim().is_empty(),
+            "{}: text outside the root",
+            name
+        );
+        let mut entities = text.split('&').skip(1);
+        assert!(
+            entities.all(|e| ["amp;", "lt;", "gt;", "quot;", "apos;"]
+                .iter()
+                .any(|entity| e.starts_with(entity))),
+            "{}: bare & in {:?}",
+            name,
+            text
+        );
+        assert!(
+            !text.chars().any(|c| c < ' ' && !"\t\n\r".contains(c)),
+            "{}: control character in {:?}",
+            name,
+            text
+        );
+    }
+
+    /// 包含 `text` 的那一段
+    fn paragraph<'a>(document: &'a str, text: &str) -> &'a str {
+        let needle = format!(">{}</w:t>", text);
+        document
+            .split("<w:p>")
+            .find(|p| p.contains(&needle))
+            .and_then(|p| p.split("</w:p>").next())
+            .unwrap_or_else(|| panic!("no paragraph with {:?}", text))
+    }
+
+    /// 某个 w:num 每一层的起始编号
+    fn starts(numbering: &str, num: usize) -> Vec<u64> {
+        let open = format!("<w:num w:numId=\"{}\">", num);
+        let from = numbering.find(&open).unwrap();
+        let to = from + numbering[from..].find("</w:num>").unwrap();
+        numbering[from..to]
+            .split("<w:startOverride w:val=\"")
+            .skip(1)
+            .map(|s| s.split('"').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    const TRICKY: &str = "# A & B\n\n\
+                          Text with \"quotes\", a\ttab and \u{1}control.\n\n\
+                          - **粗** *斜* ~~删~~ `a<b`\n  - nested\n\n\
+                          1. one\n2. two\n\n\
+                          > quote\n\n\
+                          ```\nfn main() {}\n```\n\n\
+                          ---\n";
+
+    #[test]
+    fn parts_are_well_formed() {
+        for markdown in [true, false] {
+            let docx = build("标题 & <title>", TRICKY, markdown).unwrap();
+            let parts = parts(&docx);
+            assert_eq!(parts.len(), 7);
+            for (name, xml) in &parts {
+                assert_well_formed(name, xml);
+            }
+        }
+        let core = part(&build("a & b", "", false).unwrap(), "docProps/core.xml");
+        assert!(core.contains("<dc:title>a &amp; b</dc:title>"));
+    }
+
+    #[test]
+    fn headings_lists_and_emphasis() {
+        let docx = build("t", TRICKY, true).unwrap();
+        let document = part(&docx, "word/document.xml");
+
+        assert!(paragraph(&document, "A &amp; B").contains("<w:pStyle w:val=\"Heading1\"/>"));
+        let item = paragraph(&document, "粗");
+        assert!(item.contains("<w:pStyle w:val=\"ListParagraph\"/>"));
+        assert!(item.contains("<w:numPr><w:ilvl w:val=\"0\"/><w:numId w:val=\"1\"/></w:numPr>"));
+        assert!(item.contains("<w:rPr><w:b/><w:bCs/></w:rPr><w:t xml:space=\"preserve\">粗</w:t>"));
+        assert!(item.contains("<w:rPr><w:i/><w:iCs/></w:rPr><w:t xml:space=\"preserve\">斜</w:t>"));
+        assert!(item.contains("<w:rPr><w:strike/></w:rPr><w:t xml:space=\"preserve\">删</w:t>"));
+        assert!(item.contains("<w:rStyle w:val=\"CodeChar\"/>"));
+        assert!(
+            paragraph(&document, "nested").contains("<w:ilvl w:val=\"1\"/><w:numId w:val=\"1\"/>")
+        );
+        assert!(
+            paragraph(&document, "one").contains("<w:ilvl w:val=\"0\"/><w:numId w:val=\"2\"/>")
+        );
+        assert!(paragraph(&document, "quote").contains("<w:pStyle w:val=\"Quote\"/>"));
+        assert!(paragraph(&document, "fn main() {}").contains("<w:pStyle w:val=\"CodeBlock\"/>"));
+        assert!(document.contains("<w:pStyle w:val=\"Rule\"/>"));
+        let text = paragraph(&document, "Text with &quot;quotes&quot;, a");
+        assert!(text.contains("<w:tab/><w:t xml:space=\"preserve\">tab and control.</w:t>"));
+    }
+
+    #[test]
+    fn plain_text_splits_paragraphs_on_blank_lines() {
+        let docx = build("t", "# not a heading\nsecond line\n\n\nnext", false).unwrap();
+        let document = part(&docx, "word/document.xml");
+        let first = paragraph(&document, "# not a heading");
+        assert!(!first.contains("<w:pStyle"));
+        assert!(first.contains("<w:br/>"));
+        assert_eq!(document.matches("<w:p>").count(), 2);
+    }
+
+    #[test]
+    fn ordered_lists_keep_their_start() {
+        let docx = build("t", "1. a\n2. b\n\ntext\n\n5. c\n6. d\n", true).unwrap();
+        let numbering = part(&docx, "word/numbering.xml");
+        assert_eq!(starts(&numbering, 2), [1, 1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(starts(&numbering, 3), [5, 1, 1, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn nested_ordered_list_continues_numbering() {
+        // 不是从 1 开始的列表不能打断段落，前面要空一行
+        let markdown = "1. a\n   1. x\n   2. y\n2. b\n\n   3. z\n   4. w\n3. c\n";
+        let docx = build("t", markdown, true).unwrap();
+        let document = part(&docx, "word/document.xml");
+        let numbering = part(&docx, "word/numbering.xml");
+
+        // 外层一个列表，b、c 接着 a 编号
+        for text in ["a", "b", "c"] {
+            assert!(
+                paragraph(&document, text).contains("<w:ilvl w:val=\"0\"/><w:numId w:val=\"2\"/>")
+            );
+        }
+        assert!(paragraph(&document, "x").contains("<w:ilvl w:val=\"1\"/><w:numId w:val=\"3\"/>"));
+        assert!(paragraph(&document, "w").contains("<w:ilvl w:val=\"1\"/><w:numId w:val=\"4\"/>"));
+        assert_eq!(starts(&numbering, 3)[1], 1);
+        // 第二段嵌套列表从 3 接着编
+        assert_eq!(starts(&numbering, 4)[..2], [1, 3]);
+    }
+}
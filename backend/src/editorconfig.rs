@@ -0,0 +1,133 @@
+use std::{borrow::Cow, sync::Arc};
+
+use ec4rs::property::{Charset, EndOfLine, FinalNewline, IndentSize, IndentStyle, TrimTrailingWs};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+use crate::{document::FileFormat, encoding, line_ending::LineEnding};
+
+/// `.editorconfig` 里和某个文件有关的规则，没写的就是 None
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    /// 编码和要不要 BOM（utf-8-bom）
+    pub charset: Option<(&'static Encoding, bool)>,
+    pub end_of_line: Option<LineEnding>,
+    pub insert_final_newline: Option<bool>,
+    pub trim_trailing_whitespace: Option<bool>,
+    /// Tab 键插入的内容，一个 `\t` 或者几个空格
+    pub indent: Option<String>,
+}
+
+/// 找出 `path` 适用的规则，文件还不存在也行；读不了 `.editorconfig` 就当没有
+pub async fn lookup(path: &str) -> Rules {
+    let Ok(path) = std::path::absolute(path) else {
+        return Rules::default();
+    };
+
+    tokio::task::spawn_blocking(move || match ec4rs::properties_of(&path) {
+        Ok(mut properties) => {
+            properties.use_fallbacks();
+            Rules::from(&properties)
+        }
+        Err(e) => {
+            eprintln!("Ignoring .editorconfig for {}: {}", path.display(), e);
+            Rules::default()
+        }
+    })
+    .await
+    .unwrap_or_default()
+}
+
+impl From<&ec4rs::Properties> for Rules {
+    fn from(properties: &ec4rs::Properties) -> Self {
+        let charset = properties
+            .get::<Charset>()
+            .ok()
+            .map(|charset| match charset {
+                Charset::Utf8 => (UTF_8, false),
+                Charset::Utf8Bom => (UTF_8, true),
+                // WHATWG 里 latin1 就是 windows-1252
+                Charset::Latin1 => (WINDOWS_1252, false),
+                Charset::Utf16Le => (UTF_16LE, false),
+                Charset::Utf16Be => (UTF_16BE, false),
+            });
+        let end_of_line = properties.get::<EndOfLine>().ok().map(|eol| match eol {
+            EndOfLine::Lf => LineEnding::Lf,
+            EndOfLine::CrLf => LineEnding::Crlf,
+            EndOfLine::Cr => LineEnding::Cr,
+        });
+        let indent = match properties.get::<IndentStyle>() {
+            Ok(IndentStyle::Tabs) => Some("\t".to_string()),
+            Ok(IndentStyle::Spaces) => match properties.get::<IndentSize>() {
+                Ok(IndentSize::Value(size)) => Some(" ".repeat(size)),
+                _ => Some(" ".repeat(4)),
+            },
+            Err(_) => None,
+        };
+
+        Rules {
+            charset,
+            end_of_line,
+            insert_final_newline: match properties.get::<FinalNewline>() {
+                Ok(FinalNewline::Value(value)) => Some(value),
+                Err(_) => None,
+            },
+            trim_trailing_whitespace: match properties.get::<TrimTrailingWs>() {
+                Ok(TrimTrailingWs::Value(value)) => Some(value),
+                Err(_) => None,
+            },
+            indent,
+        }
+    }
+}
+
+impl Rules {
+    /// 规则里的编码，盖过命令行的默认编码
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.charset.map(|(encoding, _)| encoding)
+    }
+
+    /// 换行符和 BOM 改成规则要求的样子；编码由调用的地方决定，用户另选了编码就不管 charset
+    pub fn apply(&self, format: &mut FileFormat) {
+        if let Some((encoding, bom)) = self.charset
+            && encoding == format.encoding
+        {
+            // utf-8 明确不要 BOM，UTF-16 没说就保持原样
+            format.bom =
+                bom || (format.bom && encoding != UTF_8 && !encoding::bom(encoding).is_empty());
+        }
+        if let Some(line_ending) = self.end_of_line
+            && line_ending != format.line_ending
+        {
+            format.line_ending = line_ending;
+            format.line_breaks = Arc::new([]);
+        }
+    }
+
+    /// 保存前去掉行尾空白、补上 / 去掉结尾的换行；内容是统一成 `\n` 的
+    pub fn apply_content<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        let mut content = Cow::Borrowed(content);
+
+        if self.trim_trailing_whitespace == Some(true)
+            && content.split('\n').any(|line| line.ends_with([' ', '\t']))
+        {
+            let trimmed: Vec<&str> = content
+                .split('\n')
+                .map(|line| line.trim_end_matches([' ', '\t']))
+                .collect();
+            content = Cow::Owned(trimmed.join("\n"));
+        }
+
+        match self.insert_final_newline {
+            Some(true) if !content.is_empty() && !content.ends_with('\n') => {
+                content.to_mut().push('\n');
+            }
+            Some(false) if content.ends_with('\n') => {
+                let end = content.trim_end_matches('\n').len();
+                content.to_mut().truncate(end);
+            }
+            _ => {}
+        }
+
+        content
+    }
+}
@@ -0,0 +1,306 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Result;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// 打开 / 新建文件时使用的编码
+///
+/// 除了 `auto` 以外接受任意 WHATWG 编码标签，例如 `utf-8`、`gbk`、`gb18030`、`big5`、
+/// `shift_jis`、`euc-kr`、`windows-1252`、`iso-8859-2`、`utf-16le`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encodes {
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+impl Encodes {
+    /// auto 还没探测过（比如新建文件）就按 UTF-8 处理
+    pub fn resolve(self, detected: Option<&'static Encoding>) -> &'static Encoding {
+        match self {
+            Encodes::Auto => detected.unwrap_or(UTF_8),
+            Encodes::Fixed(encoding) => encoding,
+        }
+    }
+}
+
+impl FromStr for Encodes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Encodes::Auto);
+        }
+
+        // replacement 只能解码不能编码，不让用
+        Encoding::for_label_no_replacement(s.as_bytes())
+            .map(Encodes::Fixed)
+            .ok_or_else(|| format!("unknown encoding label: {}", s))
+    }
+}
+
+impl fmt::Display for Encodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encodes::Auto => f.write_str("auto"),
+            Encodes::Fixed(encoding) => f.write_str(encoding.name()),
+        }
+    }
+}
+
+/// 先看 BOM，再交给 chardetng 猜
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    // NUL 在 UTF-8 里也合法，没有 BOM 的 UTF-16 英文会被当成 UTF-8，得先看 NUL 的位置
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        return encoding;
+    }
+
+    // 纯 ASCII 和合法 UTF-8 都直接当 UTF-8
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Allow);
+    detector.feed(bytes, true);
+    detector.guess(None, Utf8Detection::Allow)
+}
+
+/// 只看开头这么多字节
+const UTF16_SAMPLE: usize = 4096;
+
+/// 拉丁字母的 UTF-16 每两个字节里有一个是 0：小端在奇数位，大端在偶数位
+///
+/// 正经文本几乎不会带 NUL，另一边的 0 又很少，才认作 UTF-16
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(UTF16_SAMPLE)];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    // 至少三成是 ASCII，另一边的 0 不到一成（中文的 UTF-16 有时也会有 0）
+    let mostly = |count: usize| count * 10 >= pairs * 3;
+    let rarely = |count: usize| count * 10 < pairs;
+
+    if mostly(odd) && rarely(even) {
+        Some(UTF_16LE)
+    } else if mostly(even) && rarely(odd) {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    /// 非法字节序列在文件里的起始偏移
+    pub malformed: Vec<usize>,
+}
+
+/// 解码并记下每个非法字节序列的起始偏移，非法的地方和 encoding_rs 一样替换成 U+FFFD
+///
+/// 和 `Encoding::decode` 一样有 BOM 就以 BOM 为准
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> Decoded {
+    let (encoding, bom_len) = Encoding::for_bom(bytes).unwrap_or((encoding, 0));
+    let bytes = &bytes[bom_len..];
+
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut decoded = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length_without_replacement(bytes.len())
+            .unwrap_or(bytes.len()),
+    );
+    let mut malformed = Vec::new();
+    let mut read = 0;
+
+    loop {
+        let (result, consumed) =
+            decoder.decode_to_string_without_replacement(&bytes[read..], &mut decoded, true);
+        read += consumed;
+
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => decoded.reserve(
+                decoder
+                    .max_utf8_buffer_length_without_replacement(bytes.len() - read)
+                    .unwrap_or(bytes.len() - read)
+                    .max(4),
+            ),
+            DecoderResult::Malformed(bad, after) => {
+                malformed.push(bom_len + read.saturating_sub(bad as usize + after as usize));
+                decoded.push('\u{FFFD}');
+            }
+        }
+    }
+
+    // 指定了 UTF-8 却像是没 BOM 的 UTF-16：NUL 都算解码错误，让前端提示换编码，别当成正常文本存回去
+    if encoding == UTF_8 && utf16_without_bom(bytes).is_some() {
+        malformed.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == 0)
+                .map(|(i, _)| bom_len + i),
+        );
+        malformed.sort_unstable();
+        decoded = decoded.replace('\0', "\u{FFFD}");
+    }
+
+    Decoded {
+        text: decoded,
+        encoding,
+        bom: bom_len > 0,
+        malformed,
+    }
+}
+
+/// 只有 UTF-8 和 UTF-16 有 BOM，其余编码返回空
+pub fn bom(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_8 {
+        b"\xEF\xBB\xBF"
+    } else if encoding == UTF_16LE {
+        b"\xFF\xFE"
+    } else if encoding == UTF_16BE {
+        b"\xFE\xFF"
+    } else {
+        b""
+    }
+}
+
+pub fn encode(content: &str, encoding: &'static Encoding, with_bom: bool) -> Result<Vec<u8>> {
+    let mut encoded_bytes = Vec::new();
+    if with_bom {
+        encoded_bytes.extend_from_slice(bom(encoding));
+    }
+
+    // encoding_rs 按 WHATWG 规范会把 UTF-16 的输出编码换成 UTF-8，只能自己写
+    if encoding == UTF_16LE {
+        encoded_bytes.extend(content.encode_utf16().flat_map(u16::to_le_bytes));
+        return Ok(encoded_bytes);
+    }
+    if encoding == UTF_16BE {
+        encoded_bytes.extend(content.encode_utf16().flat_map(u16::to_be_bytes));
+        return Ok(encoded_bytes);
+    }
+
+    let (encoded, _, has_errors) = encoding.encode(content);
+
+    if has_errors {
+        return Err(Unencodable {
+            encoding,
+            character: first_unmappable(content, encoding).unwrap_or('\u{FFFD}'),
+        }
+        .into());
+    }
+
+    encoded_bytes.extend_from_slice(&encoded);
+    Ok(encoded_bytes)
+}
+
+/// 内容里有这个编码表示不了的字符
+#[derive(Debug)]
+pub struct Unencodable {
+    pub encoding: &'static Encoding,
+    /// 第一个表示不了的字符，前端拿它去定位
+    pub character: char,
+}
+
+impl fmt::Display for Unencodable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Content contains characters that cannot be encoded in {}, such as {:?}",
+            self.encoding.name(),
+            self.character
+        )
+    }
+}
+
+impl std::error::Error for Unencodable {}
+
+/// 出错了才用，再编码一遍找出是哪个字符
+fn first_unmappable(content: &str, encoding: &'static Encoding) -> Option<char> {
+    let mut encoder = encoding.new_encoder();
+    let mut buffer = Vec::with_capacity(4096);
+    let mut read = 0;
+
+    loop {
+        buffer.clear();
+        let (result, consumed) = encoder.encode_from_utf8_to_vec_without_replacement(
+            &content[read..],
+            &mut buffer,
+            true,
+        );
+        read += consumed;
+
+        match result {
+            EncoderResult::InputEmpty => return None,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => return Some(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn ascii_utf16_without_bom() {
+        // 这两种都是合法的 UTF-8
+        assert_eq!(detect(&utf16le("Hello, world\n")), UTF_16LE);
+        assert_eq!(detect(&utf16be("Hello, world\n")), UTF_16BE);
+    }
+
+    #[test]
+    fn mixed_utf16_without_bom() {
+        assert_eq!(detect(&utf16le("第一章 Chapter One\n")), UTF_16LE);
+    }
+
+    #[test]
+    fn plain_utf8_stays_utf8() {
+        assert_eq!(detect(b"Hello, world\n"), UTF_8);
+        assert_eq!(detect("你好，世界".as_bytes()), UTF_8);
+        assert_eq!(detect(b""), UTF_8);
+    }
+
+    #[test]
+    fn utf16_read_as_utf8_is_malformed() {
+        let decoded = decode(&utf16le("Hi"), UTF_8);
+        assert_eq!(decoded.malformed, vec![1, 3]);
+        assert_eq!(decoded.text, "H\u{FFFD}i\u{FFFD}");
+
+        let decoded = decode(b"Hi", UTF_8);
+        assert!(decoded.malformed.is_empty());
+    }
+
+    #[test]
+    fn bom_wins() {
+        let mut bytes = b"\xFF\xFE".to_vec();
+        bytes.extend(utf16le("hi"));
+        assert_eq!(detect(&bytes), UTF_16LE);
+    }
+}
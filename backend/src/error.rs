@@ -0,0 +1,208 @@
+use axum::{
+    Json,
+    extract::rejection::{JsonRejection, StringRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{Data, browse::OutsideRoot, encoding::Unencodable, streaming::NotUtf8};
+
+/// API 出错时返回给前端的错误，body 是 `{"error": {"kind": ..., "message": ...}}`
+///
+/// 出错时不能再返回 200 加一个 `saved: false`：内容里塞着报错信息，一按保存就把原文件冲掉了
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    PermissionDenied(String),
+    /// 文件没按这个编码解开，只读，不让保存
+    Decode {
+        message: String,
+        /// 非法字节序列的起始偏移
+        offsets: Vec<usize>,
+    },
+    /// 内容里有目标编码表示不了的字符
+    Encode {
+        message: String,
+        encoding: String,
+        character: char,
+    },
+    /// 磁盘上的文件和前端手里的版本对不上，或者目标文件已经存在
+    Conflict {
+        message: String,
+        /// 磁盘上现在的内容，读得到的话
+        current: Option<Box<Data>>,
+    },
+    /// 用户关掉了对话框
+    Cancelled,
+    /// 请求体超过了 --max-body
+    TooLarge(String),
+    /// headless 时弹不了对话框，要前端自己选好路径再来
+    PathRequired,
+    /// 没带启动时打印的令牌 / 密码，或者不对
+    Unauthorized(String),
+    BadRequest(String),
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail {
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offsets: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    character: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Box<Data>>,
+}
+
+impl AppError {
+    pub fn conflict(message: impl Into<String>, current: Option<Data>) -> Self {
+        AppError::Conflict {
+            message: message.into(),
+            current: current.map(Box::new),
+        }
+    }
+
+    pub fn too_large() -> Self {
+        AppError::TooLarge(
+            "The document is larger than the server accepts, start it with a larger --max-body"
+                .into(),
+        )
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            AppError::Decode { .. } | AppError::Encode { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Cancelled | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::PathRequired => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::PermissionDenied(_) => "permission_denied",
+            AppError::Decode { .. } => "decode",
+            AppError::Encode { .. } => "encode",
+            AppError::Conflict { .. } => "conflict",
+            AppError::Cancelled => "cancelled",
+            AppError::TooLarge(_) => "too_large",
+            AppError::PathRequired => "path_required",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> Self {
+        // 带着 context 的话 {:#} 会把整条链都打出来
+        let message = format!("{:#}", e);
+
+        if let Some(e) = e.downcast_ref::<Unencodable>() {
+            return AppError::Encode {
+                message,
+                encoding: e.encoding.name().to_string(),
+                character: e.character,
+            };
+        }
+        if e.downcast_ref::<OutsideRoot>().is_some() {
+            return AppError::PermissionDenied(message);
+        }
+        if e.downcast_ref::<NotUtf8>().is_some() {
+            return AppError::BadRequest(message);
+        }
+
+        match e
+            .chain()
+            .find_map(|e| e.downcast_ref::<std::io::Error>())
+            .map(|e| e.kind())
+        {
+            Some(std::io::ErrorKind::NotFound) => AppError::NotFound(message),
+            Some(std::io::ErrorKind::PermissionDenied) => AppError::PermissionDenied(message),
+            _ => AppError::Internal(message),
+        }
+    }
+}
+
+/// axum 提取请求体失败（太大、不是合法的 JSON 之类），也换成同样格式的错误
+fn rejection(status: StatusCode, body: String) -> AppError {
+    if status == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::too_large()
+    } else {
+        AppError::BadRequest(body)
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(e: JsonRejection) -> Self {
+        rejection(e.status(), e.body_text())
+    }
+}
+
+impl From<StringRejection> for AppError {
+    fn from(e: StringRejection) -> Self {
+        rejection(e.status(), e.body_text())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let kind = self.kind();
+
+        let mut detail = ErrorDetail {
+            kind,
+            message: String::new(),
+            offsets: None,
+            encoding: None,
+            character: None,
+            current: None,
+        };
+        detail.message = match self {
+            AppError::NotFound(message)
+            | AppError::PermissionDenied(message)
+            | AppError::TooLarge(message)
+            | AppError::Unauthorized(message)
+            | AppError::BadRequest(message)
+            | AppError::Internal(message) => message,
+            AppError::Decode { message, offsets } => {
+                detail.offsets = Some(offsets);
+                message
+            }
+            AppError::Encode {
+                message,
+                encoding,
+                character,
+            } => {
+                detail.encoding = Some(encoding);
+                detail.character = Some(character);
+                message
+            }
+            AppError::Conflict { message, current } => {
+                detail.current = current;
+                message
+            }
+            AppError::Cancelled => "Cancelled".into(),
+            AppError::PathRequired => "No file dialog in headless mode, choose a path".into(),
+        };
+
+        (status, Json(ErrorBody { error: detail })).into_response()
+    }
+}
@@ -0,0 +1,74 @@
+use std::convert::Infallible;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, WatchStream},
+};
+
+use crate::shutdown::Client;
+
+/// 推给前端的文件变化，SSE 的 event 名就是 type
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FileEvent {
+    /// 被外部程序改了
+    Modified {
+        revision: Option<String>,
+    },
+    Removed,
+    Renamed {
+        to: Option<String>,
+    },
+    /// 保存成功，其他标签页可以据此刷新
+    Saved {
+        revision: String,
+    },
+    /// 换了一个文档（打开别的文件或者新建）
+    Opened {
+        title: String,
+    },
+}
+
+impl FileEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            FileEvent::Modified { .. } => "modified",
+            FileEvent::Removed => "removed",
+            FileEvent::Renamed { .. } => "renamed",
+            FileEvent::Saved { .. } => "saved",
+            FileEvent::Opened { .. } => "opened",
+        }
+    }
+}
+
+/// 要退出时连接得自己结束，不然优雅退出会一直等着这些长连接
+pub fn stream(
+    rx: broadcast::Receiver<FileEvent>,
+    shutdown: watch::Receiver<bool>,
+    client: Client,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let shutdown = WatchStream::new(shutdown)
+        .filter(|shutdown| *shutdown)
+        .map(|_| None);
+
+    // 跟不上（Lagged）就丢掉旧事件，前端收到下一条还会重新加载
+    let stream = BroadcastStream::new(rx)
+        .map(Some)
+        .merge(shutdown)
+        .take_while(Option::is_some)
+        .filter_map(move |event| {
+            // 跟着 stream 一起丢掉，连接数就减一了
+            let _client = &client;
+            let event = event?.ok()?;
+            Event::default()
+                .event(event.name())
+                .json_data(&event)
+                .ok()
+                .map(Ok)
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,385 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::error::AppError;
+
+/// 前端带令牌用的请求头，EventSource 设不了请求头，就放在 `?token=` 里
+pub const TOKEN_HEADER: &str = "x-simply-writer-token";
+
+/// 密码错了之后拖一会再回，别让人在局域网里飞快地猜
+const WRONG_PASSWORD_DELAY: Duration = Duration::from_millis(500);
+
+/// 每次启动随机生成的令牌，打印在启动地址里
+///
+/// 别的网页能往 127.0.0.1 发请求，但拿不到这个令牌
+#[derive(Clone)]
+pub struct Guard {
+    token: Arc<str>,
+    /// 设了的话所有请求都要先过 HTTP Basic 认证，用户名随便填
+    password: Option<Arc<str>>,
+    /// `--tls-name` 给的主机名，用户自己起的名字不会是攻击者的域名
+    names: Arc<[String]>,
+}
+
+impl Guard {
+    pub fn new(password: Option<String>, names: &[String]) -> Result<Self> {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).context("Failed to generate the access token")?;
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(Guard {
+            token: token.into(),
+            password: password.map(Arc::from),
+            names: names.iter().map(|name| name.trim().to_string()).collect(),
+        })
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(password) = &self.password else {
+            return true;
+        };
+        header_str(headers, header::AUTHORIZATION.as_str())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| {
+                credentials
+                    .split_once(':')
+                    .map(|(_, given)| same(given, password))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// 所有请求都要过的检查
+///
+/// `Host` 只认本机地址、IP 和 `--tls-name` 给的名字，挡住 DNS rebinding；设了密码要先过认证；
+/// 带了 `Origin` 的必须和 `Host` 同源；`/api/` 下的还要带对令牌
+pub async fn check(
+    State(guard): State<Guard>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let headers = request.headers();
+
+    // HTTP/2 没有 Host 头，地址在 :authority 里
+    let host = header_str(headers, header::HOST.as_str())
+        .or_else(|| request.uri().authority().map(|a| a.as_str()))
+        .ok_or_else(|| AppError::PermissionDenied("Missing Host header".into()))?;
+    if !guard.allowed_host(host_name(host)) {
+        return Err(AppError::PermissionDenied(format!(
+            "Host {} is not allowed",
+            host
+        )));
+    }
+
+    if !guard.authorized(headers) {
+        if headers.contains_key(header::AUTHORIZATION) {
+            tokio::time::sleep(WRONG_PASSWORD_DELAY).await;
+        }
+        // 浏览器看到这个头会自己弹出输入密码的框
+        let mut response =
+            AppError::Unauthorized("Wrong or missing password".into()).into_response();
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"simply-writer\", charset=\"UTF-8\""),
+        );
+        return Ok(response);
+    }
+
+    if let Some(origin) = header_str(headers, header::ORIGIN.as_str()) {
+        let same_origin = origin
+            .split_once("://")
+            .is_some_and(|(scheme, rest)| matches!(scheme, "http" | "https") && rest == host);
+        if !same_origin {
+            return Err(AppError::PermissionDenied(format!(
+                "Origin {} is not allowed",
+                origin
+            )));
+        }
+    }
+
+    if request.uri().path().starts_with("/api/") {
+        let token =
+            header_str(headers, TOKEN_HEADER).or_else(|| query_token(request.uri().query()));
+        if !token.is_some_and(|token| same(token, &guard.token)) {
+            return Err(AppError::Unauthorized(
+                "Missing or wrong access token, open the URL printed at startup".into(),
+            ));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// 逐字节比较不提前退出，别从响应时间里猜出令牌 / 密码
+fn same(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn query_token(query: Option<&str>) -> Option<&str> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+}
+
+/// `Host` 去掉端口，IPv6 去掉方括号
+fn host_name(host: &str) -> &str {
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split_once(']').map_or(rest, |(name, _)| name);
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+impl Guard {
+    /// DNS rebinding 要靠攻击者自己的域名，直接用 IP 访问的不会是它
+    fn allowed_host(&self, name: &str) -> bool {
+        // 主机名结尾可以带个点
+        let name = name.strip_suffix('.').unwrap_or(name);
+        name.eq_ignore_ascii_case("localhost")
+            || name.parse::<IpAddr>().is_ok()
+            || self
+                .names
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, http::StatusCode, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const HOST: &str = "127.0.0.1:3000";
+
+    fn app(password: Option<&str>) -> (Router, String) {
+        with_names(password, &[])
+    }
+
+    fn with_names(password: Option<&str>, names: &[&str]) -> (Router, String) {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let guard = Guard::new(password.map(String::from), &names).unwrap();
+        let token = guard.token().to_string();
+        let app = Router::new()
+            .route("/", get(|| async { "page" }))
+            .route("/api/status", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(guard, check));
+        (app, token)
+    }
+
+    async fn send(app: &Router, uri: &str, headers: &[(&str, &str)]) -> StatusCode {
+        let mut request = Request::get(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[tokio::test]
+    async fn rebinding_host_is_rejected() {
+        let (app, token) = app(None);
+        let uri = format!("/api/status?token={}", token);
+        assert_eq!(
+            send(&app, &uri, &[("host", "evil.com:3000")]).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, "/", &[("host", "evil.com")]).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send(&app, "/", &[("host", HOST)]).await, StatusCode::OK);
+        assert_eq!(
+            send(&app, "/", &[("host", "LocalHost:3000")]).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn tls_names_are_allowed() {
+        let (app, _) = with_names(None, &["mybox.local"]);
+        for host in ["mybox.local:3000", "MyBox.local", "mybox.local.:3000"] {
+            assert_eq!(
+                send(&app, "/", &[("host", host)]).await,
+                StatusCode::OK,
+                "{}",
+                host
+            );
+        }
+        assert_eq!(
+            send(
+                &app,
+                "/",
+                &[
+                    ("host", "mybox.local:3000"),
+                    ("origin", "https://mybox.local:3000")
+                ]
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, "/", &[("host", "evil.com:3000")]).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, "/", &[("host", "mybox.local.evil.com")]).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn bracketed_ipv6_host() {
+        let (app, _) = app(None);
+        assert_eq!(
+            send(&app, "/", &[("host", "[::1]:3000")]).await,
+            StatusCode::OK
+        );
+        assert_eq!(send(&app, "/", &[("host", "[::1]")]).await, StatusCode::OK);
+        assert_eq!(
+            send(
+                &app,
+                "/",
+                &[("host", "[::1]:3000"), ("origin", "http://[::1]:3000")]
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(host_name("[fe80::1]:3000"), "fe80::1");
+        assert_eq!(host_name("192.168.1.5:3000"), "192.168.1.5");
+        assert_eq!(host_name("localhost"), "localhost");
+    }
+
+    #[tokio::test]
+    async fn cross_origin_is_rejected() {
+        let (app, _) = app(None);
+        for origin in [
+            "null",
+            "http://evil.com",
+            "http://127.0.0.1:3001",
+            "file://",
+        ] {
+            assert_eq!(
+                send(&app, "/", &[("host", HOST), ("origin", origin)]).await,
+                StatusCode::FORBIDDEN,
+                "{}",
+                origin
+            );
+        }
+        for origin in ["http://127.0.0.1:3000", "https://127.0.0.1:3000"] {
+            assert_eq!(
+                send(&app, "/", &[("host", HOST), ("origin", origin)]).await,
+                StatusCode::OK
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn api_needs_the_token() {
+        let (app, token) = app(None);
+        let host = ("host", HOST);
+        assert_eq!(
+            send(&app, "/api/status", &[host]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, "/api/status?token=wrong", &[host]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, "/api/status", &[host, (TOKEN_HEADER, "wrong")]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // 只差最后一位
+        let mut almost = token.clone();
+        almost.pop();
+        almost.push('x');
+        assert_eq!(
+            send(&app, &format!("/api/status?token={}", almost), &[host]).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            send(&app, "/api/status", &[host, (TOKEN_HEADER, &token)]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, &format!("/api/status?a=1&token={}", token), &[host]).await,
+            StatusCode::OK
+        );
+        // 页面本身不用令牌
+        assert_eq!(send(&app, "/", &[host]).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn basic_auth() {
+        let (app, token) = app(Some("secret"));
+        let uri = format!("/api/status?token={}", token);
+        let host = ("host", HOST);
+
+        assert_eq!(send(&app, "/", &[host]).await, StatusCode::UNAUTHORIZED);
+        let wrong = basic("me:wrong");
+        assert_eq!(
+            send(&app, &uri, &[host, ("authorization", &wrong)]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let no_colon = basic("secret");
+        assert_eq!(
+            send(&app, &uri, &[host, ("authorization", &no_colon)]).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // 用户名随便填
+        for credentials in ["me:secret", ":secret"] {
+            let right = basic(credentials);
+            assert_eq!(
+                send(&app, &uri, &[host, ("authorization", &right)]).await,
+                StatusCode::OK
+            );
+        }
+        // 密码对了也还要令牌
+        let right = basic("me:secret");
+        assert_eq!(
+            send(&app, "/api/status", &[host, ("authorization", &right)]).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_password_prompts_the_browser() {
+        let (app, _) = app(Some("secret"));
+        let request = Request::get("/")
+            .header("host", HOST)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+}
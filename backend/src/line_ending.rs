@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+/// 文件用的换行符，浏览器那边的 textarea 只认 `\n`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+    /// 混用，保存时按原来每一处换行的顺序还原
+    Mixed,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf | LineEnding::Mixed => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// 找出文本里每一处换行符，返回整体判断和逐处的记录（只有 mixed 才需要后者）
+pub fn detect(text: &str) -> (LineEnding, Vec<LineEnding>) {
+    let bytes = text.as_bytes();
+    let mut breaks = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                breaks.push(LineEnding::Crlf);
+                i += 1;
+            }
+            b'\r' => breaks.push(LineEnding::Cr),
+            b'\n' => breaks.push(LineEnding::Lf),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    match breaks.first() {
+        None => (LineEnding::default(), Vec::new()),
+        Some(first) if breaks.iter().all(|b| b == first) => (*first, Vec::new()),
+        Some(_) => (LineEnding::Mixed, breaks),
+    }
+}
+
+/// 统一成 `\n` 交给前端
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// 把 `\n` 换回文件原本的换行符；mixed 时超出原有数量的换行用出现最多的那种
+pub fn restore<'a>(text: &'a str, ending: LineEnding, original: &[LineEnding]) -> Cow<'a, str> {
+    match ending {
+        LineEnding::Lf => Cow::Borrowed(text),
+        LineEnding::Crlf | LineEnding::Cr => Cow::Owned(text.replace('\n', ending.as_str())),
+        LineEnding::Mixed => {
+            let fallback = most_common(original);
+            let mut restored = String::with_capacity(text.len() + original.len());
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    restored.push_str(original.get(i - 1).unwrap_or(&fallback).as_str());
+                }
+                restored.push_str(line);
+            }
+            Cow::Owned(restored)
+        }
+    }
+}
+
+/// mixed 时新加的换行用原文件里出现最多的那种
+pub fn most_common(original: &[LineEnding]) -> LineEnding {
+    [LineEnding::Crlf, LineEnding::Cr, LineEnding::Lf]
+        .into_iter()
+        .max_by_key(|e| original.iter().filter(|o| *o == e).count())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 读进来统一成 `\n`，存回去还是原来的字节
+    fn round_trip(text: &str) -> (LineEnding, String) {
+        let (ending, breaks) = detect(text);
+        let normalized = normalize(text);
+        assert!(!normalized.contains('\r'));
+        (ending, restore(&normalized, ending, &breaks).into_owned())
+    }
+
+    #[test]
+    fn lf() {
+        assert_eq!(round_trip("a\nb\n"), (LineEnding::Lf, "a\nb\n".into()));
+    }
+
+    #[test]
+    fn crlf() {
+        assert_eq!(
+            round_trip("a\r\nb\r\n"),
+            (LineEnding::Crlf, "a\r\nb\r\n".into())
+        );
+    }
+
+    #[test]
+    fn cr() {
+        assert_eq!(round_trip("a\rb\r"), (LineEnding::Cr, "a\rb\r".into()));
+    }
+
+    #[test]
+    fn mixed() {
+        let text = "a\r\nb\nc\rd\r\n";
+        assert_eq!(round_trip(text), (LineEnding::Mixed, text.into()));
+        // \r 后面紧跟 \r\n 是两处换行
+        let text = "a\r\r\nb\n";
+        assert_eq!(round_trip(text), (LineEnding::Mixed, text.into()));
+    }
+
+    #[test]
+    fn no_line_breaks() {
+        assert_eq!(round_trip(""), (LineEnding::Lf, "".into()));
+        assert_eq!(round_trip("一行"), (LineEnding::Lf, "一行".into()));
+        assert!(matches!(normalize("一行"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn mixed_uses_the_most_common_for_new_lines() {
+        let (ending, breaks) = detect("a\r\nb\r\nc\n");
+        assert_eq!(
+            restore("a\nb\nc\nd\ne", ending, &breaks),
+            "a\r\nb\r\nc\nd\r\ne"
+        );
+        // 删掉了几行，剩下的按原来的顺序
+        assert_eq!(restore("a\nb", ending, &breaks), "a\r\nb");
+    }
+}
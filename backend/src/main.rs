@@ -1,37 +1,261 @@
-use std::sync::Arc;
+mod atomic_write;
+mod backup;
+mod browse;
+mod config;
+mod document;
+mod docx;
+mod editorconfig;
+mod encoding;
+mod error;
+mod events;
+mod guard;
+mod line_ending;
+mod patch;
+mod progress;
+mod revision;
+mod shutdown;
+mod stats;
+mod streaming;
+mod tls;
+mod watcher;
 
-use tokio::sync::OnceCell;
+use std::{
+    borrow::Cow,
+    io::IsTerminal,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
 
-use anyhow::Result;
-use axum::{Json, Router, extract::State, http::StatusCode, response::Html, routing::get};
-use clap::{Parser, ValueEnum};
-use encoding_rs::{GBK, UTF_8};
+use tokio::{
+    net::TcpListener,
+    sync::{Mutex, RwLock, broadcast, watch},
+};
+use tokio_stream::StreamExt;
+
+use anyhow::Context;
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Query, State, rejection::JsonRejection},
+    http::{HeaderValue, StatusCode, header},
+    middleware,
+    response::sse::{Event, Sse},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post, put},
+};
+use clap::{CommandFactory, FromArgMatches, Parser, parser::ValueSource};
 use rfd::FileDialog;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
-enum Encodes {
-    #[value(name = "utf-8")]
-    Utf8,
-    #[value(name = "gbk")]
-    Gbk,
-}
+use backup::{BackupMode, BackupPolicy};
+use document::{
+    Document, FileFormat, Loaded, OpenedFile, read_with_encoding, title_of, write_with_encoding,
+};
+use editorconfig::Rules;
+use encoding::Encodes;
+use error::AppError;
+use events::FileEvent;
+use guard::Guard;
+use line_ending::LineEnding;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Data {
     content: String,
     title: String,
     saved: bool,
+    #[serde(default)]
+    encoding: Option<String>,
+    /// 解码出错又没同意有损转换时只读，防止 U+FFFD 被写回去
+    #[serde(default)]
+    read_only: bool,
+    /// 非法字节序列的起始偏移，最多 MAX_REPORTED_DECODE_ERRORS 个
+    #[serde(default)]
+    decode_errors: Vec<usize>,
+    /// 不传就保持文件原来的样子
+    #[serde(default)]
+    bom: Option<bool>,
+    #[serde(default)]
+    line_ending: Option<LineEnding>,
+    /// 读取 / 保存时文件的版本号，保存时对不上说明文件被别人改过
+    #[serde(default)]
+    revision: Option<String>,
+    /// 有路径但文件还不存在，第一次保存时创建
+    #[serde(default)]
+    new_file: bool,
+    /// Tab 键插入的内容，来自 .editorconfig 的 indent_style
+    #[serde(default)]
+    indent: Option<String>,
+    /// 内容太大没放进 `content` 时的总行数，按 `/api/content/lines` 分段取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_lines: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct LoadParams {
+    /// 换一种编码重新打开
+    encoding: Option<String>,
+    /// 明知有解码错误也要编辑
+    #[serde(default)]
+    lossy: bool,
+    /// 内容太大的话只回别的信息，内容让前端按行分段取
+    #[serde(default)]
+    chunked: bool,
+}
+
+/// 保存时除了内容以外前端带过来的东西，大文件走 PUT 时放在 query 里
+#[derive(Deserialize, Debug, Default)]
+struct SaveParams {
+    revision: Option<String>,
+    bom: Option<bool>,
+    line_ending: Option<LineEnding>,
+}
+
+/// 保存成功后文件的样子，大文件保存时不用再把内容传回去
+#[derive(Serialize, Debug)]
+struct Saved {
+    title: String,
+    encoding: String,
+    bom: bool,
+    line_ending: LineEnding,
+    revision: String,
+    indent: Option<String>,
+    /// 按 .editorconfig 去掉了行尾空白、改了结尾换行的话，实际写进去的内容
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    /// 大文件被 .editorconfig 改过，内容不传回去，前端按 `/api/content/lines` 重新取
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    reload: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct PatchParams {
+    /// 这些修改是在哪个版本上做的
+    revision: Option<String>,
+    edits: Vec<patch::Edit>,
+    #[serde(default)]
+    bom: Option<bool>,
+    #[serde(default)]
+    line_ending: Option<LineEnding>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct LinesParams {
+    /// 从第几行开始，从 0 数
+    #[serde(default)]
+    start: usize,
+    count: Option<usize>,
+    encoding: Option<String>,
+    /// 分段加载时带上第一段拿到的版本号，和记着的内容对得上就不用每段都重新读文件
+    revision: Option<String>,
+}
+
+/// 文件的一段行，大文件可以一段一段地取
+///
+/// 按 `\n` 切行，所有行用 `\n` 连起来就是完整的内容（以换行结尾的话最后一行是空的）
+#[derive(Serialize, Debug)]
+struct Lines {
+    title: String,
+    encoding: String,
+    revision: String,
+    start: usize,
+    /// 一共多少行
+    total: usize,
+    lines: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenParams {
+    /// 不给路径就弹系统的打开对话框
+    path: Option<String>,
+    encoding: Option<String>,
+    #[serde(default)]
+    lossy: bool,
+    /// 同 LoadParams::chunked
+    #[serde(default)]
+    chunked: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct NewParams {
+    encoding: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SaveAsParams {
+    content: String,
+    /// 不给路径就弹系统的另存为对话框
+    path: Option<String>,
+    /// 换一种编码保存，不给就沿用当前文档的
+    encoding: Option<String>,
+    /// 只存一份副本，继续编辑原来的文件
+    #[serde(default)]
+    copy: bool,
+    /// 目标文件已经存在时要明确同意才覆盖（对话框自己会问）
+    #[serde(default)]
+    overwrite: bool,
+    #[serde(default)]
+    bom: Option<bool>,
+    #[serde(default)]
+    line_ending: Option<LineEnding>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ProgressParams {
+    // 最近几天的记录
+    days: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ListParams {
+    /// 相对于 --save-dir，不给就是根目录
+    #[serde(default)]
+    path: String,
 }
 
 #[derive(Clone)]
 struct AppState {
-    file_path: Arc<OnceCell<String>>,
-    encoding: Arc<OnceCell<Encodes>>,
+    document: Arc<RwLock<Document>>,
+    // 命令行指定的默认编码
+    encoding: Encodes,
+    backup: Arc<BackupPolicy>,
+    // 两个标签页同时保存时，比对版本号和写入要一气呵成；切换文档也要等保存完
+    save_lock: Arc<Mutex<()>>,
+    events: broadcast::Sender<FileEvent>,
+    // 换了文档就换个文件监听
+    watched_path: watch::Sender<Option<String>>,
+    // 没有桌面环境，弹不出对话框，路径由前端自己选
+    headless: bool,
+    // 前端能浏览、能存进去的根目录，canonicalize 过
+    save_dir: Option<Arc<PathBuf>>,
+    // headless 时新文档第一次保存存到这里
+    default_path: Option<String>,
+    // 新文件的初始内容
+    template: Option<Arc<str>>,
+    // 配置文件 [editor] 里的编辑器偏好
+    editor: Arc<config::Editor>,
+    // 连着的标签页数
+    clients: watch::Sender<usize>,
+    // 变成 true 就开始退出
+    shutdown: watch::Sender<bool>,
+    // 每次保存后的字数记录
+    progress: Arc<progress::Store>,
+    // 请求体最大多少字节，0 是不限；直接读 Body 的 PUT /api/content/raw 要自己数
+    max_body: usize,
+    // 命令行或环境变量给的目标，对所有文档都算数；没给的按当前文档所在目录的配置来
+    goals: progress::Goals,
 }
 
 const INDEX_HTML: &str = include_str!("../../frontend-web/dist/index.html");
 const DEFAULT_FILE_NAME: &str = "Untitled";
+const MAX_REPORTED_DECODE_ERRORS: usize = 64;
+const DEFAULT_LINES_COUNT: usize = 1000;
+/// 前端要分段加载时，超过这么多字节的内容不放进 JSON
+const LARGE_DOCUMENT: usize = 1 << 20;
+/// 流式保存时攒够这么多字节写一次临时文件
+const STREAM_WRITE_SIZE: usize = 1 << 16;
+const DEFAULT_PROGRESS_DAYS: usize = 30;
+const MAX_PROGRESS_DAYS: usize = 366;
 
 #[derive(Parser, Debug)]
 #[command(version, about = "A simply web ui note")]
@@ -39,96 +263,745 @@ struct Args {
     /// Path to the text file
     path: Option<String>,
 
-    #[arg(short, long, default_value_t = 3000)]
-    /// Port to listen on
+    #[arg(short, long, default_value = "3000", value_parser = parse_port, env = "SIMPLY_WRITER_PORT")]
+    /// Port to listen on, tries the next ones if it's taken ("auto" or 0 = any free port)
     port: u16,
 
-    #[arg(short, long, default_value = "utf-8")]
-    // Use which encode to create / open file
+    #[arg(
+        long = "host",
+        value_name = "ADDR",
+        default_value = "127.0.0.1",
+        value_delimiter = ',',
+        env = "SIMPLY_WRITER_HOST"
+    )]
+    /// Address to listen on, repeat or separate with commas for several (e.g. 0.0.0.0, ::)
+    hosts: Vec<IpAddr>,
+
+    #[arg(long, env = "SIMPLY_WRITER_PASSWORD", hide_env_values = true)]
+    /// Password for HTTP Basic auth, required when listening on a non-loopback address
+    password: Option<String>,
+
+    #[arg(long, env = "SIMPLY_WRITER_TLS")]
+    /// Serve over HTTPS with a self-signed certificate, generated on first run and kept for later
+    tls: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_key",
+        env = "SIMPLY_WRITER_TLS_CERT"
+    )]
+    /// Use this PEM certificate (chain) for HTTPS instead of a self-signed one, implies --tls
+    tls_cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_cert",
+        env = "SIMPLY_WRITER_TLS_KEY"
+    )]
+    /// Private key (PEM) for --tls-cert
+    tls_key: Option<PathBuf>,
+
+    #[arg(
+        long = "tls-name",
+        value_name = "NAME",
+        value_delimiter = ',',
+        env = "SIMPLY_WRITER_TLS_NAME"
+    )]
+    /// Host name to accept requests for, e.g. mybox.local, also put into the self-signed certificate (repeatable)
+    tls_names: Vec<String>,
+
+    #[arg(short, long, default_value = "utf-8", env = "SIMPLY_WRITER_ENCODING")]
+    /// Use which encode to create / open file (any WHATWG label, or "auto")
     encoding: Encodes,
+
+    #[arg(long, value_enum, default_value_t = BackupMode::Tilde, env = "SIMPLY_WRITER_BACKUP")]
+    /// Keep the previous version before every save
+    backup: BackupMode,
+
+    #[arg(
+        long,
+        default_value = ".simply-writer/backups",
+        env = "SIMPLY_WRITER_BACKUP_DIR"
+    )]
+    /// Backup directory for `--backup dir`, relative to the file's directory
+    backup_dir: std::path::PathBuf,
+
+    #[arg(long, default_value_t = 10, env = "SIMPLY_WRITER_BACKUP_KEEP")]
+    /// How many backups to keep per file in `--backup dir` mode (0 = unlimited)
+    backup_keep: usize,
+
+    #[arg(long, env = "SIMPLY_WRITER_BACKUP_MAX_AGE")]
+    /// Delete backups older than this many days in `--backup dir` mode
+    backup_max_age: Option<u64>,
+
+    #[arg(long, env = "SIMPLY_WRITER_HEADLESS")]
+    /// Never open native file dialogs, let the browser pick paths under --save-dir instead
+    headless: bool,
+
+    #[arg(long, env = "SIMPLY_WRITER_SAVE_DIR")]
+    /// Directory the browser may list and save into [default in headless mode: current directory]
+    save_dir: Option<PathBuf>,
+
+    #[arg(long, env = "SIMPLY_WRITER_DEFAULT_PATH")]
+    /// Where to save a new untitled document in headless mode, relative to --save-dir
+    default_path: Option<String>,
+
+    #[arg(long, env = "SIMPLY_WRITER_TEMPLATE")]
+    /// Start new files (and files that don't exist yet) from this file's content
+    template: Option<PathBuf>,
+
+    #[arg(long, default_value = "64M", value_parser = parse_size, env = "SIMPLY_WRITER_MAX_BODY")]
+    /// Largest request body accepted when saving, e.g. 512K, 64M, 1G (0 = unlimited)
+    max_body: usize,
+
+    #[arg(long, value_name = "WORDS", env = "SIMPLY_WRITER_DAILY_GOAL")]
+    /// Words to write each day, shown as progress and used for streaks
+    daily_goal: Option<usize>,
+
+    #[arg(long, value_name = "WORDS", env = "SIMPLY_WRITER_TARGET")]
+    /// Word count the document should reach, shown as progress
+    target: Option<usize>,
+
+    #[arg(long, env = "SIMPLY_WRITER_NO_PAUSE")]
+    /// Exit right away on startup errors instead of waiting for a key press
+    no_pause: bool,
+
+    #[arg(long, env = "SIMPLY_WRITER_EXIT_ON_CLOSE")]
+    /// Shut down once the last browser tab is closed
+    exit_on_close: bool,
 }
 
-async fn read_with_encoding(path: &str, encoding: &Encodes) -> Result<String> {
-    let bytes = tokio::fs::read(path).await?;
+fn parse_port(s: &str) -> Result<u16, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(0);
+    }
+    s.parse().map_err(|_| format!("invalid port: {}", s))
+}
 
-    let encoder: &'static encoding_rs::Encoding = match encoding {
-        Encodes::Utf8 => UTF_8,
-        Encodes::Gbk => GBK,
+/// `64M` 这样的大小，按 1024 进位
+fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: usize = number.parse().map_err(|_| format!("invalid size: {}", s))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err(format!("unknown size unit: {}", unit)),
     };
 
-    let (decoded, _, _has_errors) = encoder.decode(&bytes);
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size too large: {}", s))
+}
 
-    // 如果这里丢编码错误，编码对不上就返回空字符串 (unwrap_or_default) ，如果按下保存就会顶掉原本的信息
-    // if has_errors {
-    //     anyhow::bail!("Failed to decode file at {} using {:?}", path, encoding);
-    // }
+fn parse_encoding(label: &str) -> Result<Encodes, AppError> {
+    label.parse::<Encodes>().map_err(AppError::BadRequest)
+}
 
-    Ok(decoded.into_owned())
+/// 没指定就沿用上次打开时的编码，不然每次重新加载都要重新探测 / 丢掉用户的选择
+fn encoding_for(
+    state: &AppState,
+    opened: Option<&OpenedFile>,
+    rules: &Rules,
+    label: Option<&str>,
+) -> Result<Encodes, AppError> {
+    match label {
+        Some(label) => parse_encoding(label),
+        None => Ok(opened
+            .map(|o| Encodes::Fixed(o.format.encoding))
+            .unwrap_or(default_encoding(state, rules))),
+    }
 }
 
-async fn write_with_encoding(path: &str, content: &str, encoding: &Encodes) -> Result<()> {
-    let encoder = match encoding {
-        Encodes::Utf8 => encoding_rs::UTF_8,
-        Encodes::Gbk => encoding_rs::GBK,
-    };
+/// .editorconfig 的 charset 盖过命令行的默认编码
+fn default_encoding(state: &AppState, rules: &Rules) -> Encodes {
+    rules
+        .encoding()
+        .map(Encodes::Fixed)
+        .unwrap_or(state.encoding)
+}
 
-    let (encoded_bytes, _, has_errors) = encoder.encode(content);
+/// 还没有文件的路径上新建文档用的格式
+fn new_format(encode: Encodes, rules: &Rules) -> FileFormat {
+    let mut format = FileFormat::new(encode.resolve(None));
+    rules.apply(&mut format);
+    format
+}
 
-    if has_errors {
-        anyhow::bail!(
-            "Content contains characters that cannot be encoded in {:?}",
-            encoding
+/// 前端传来的路径，有 --save-dir 时只能落在它里面
+async fn client_path(state: &AppState, path: &str) -> Result<String, AppError> {
+    match &state.save_dir {
+        Some(root) => Ok(browse::resolve(root, path)
+            .await?
+            .to_string_lossy()
+            .to_string()),
+        None => Ok(path.to_string()),
+    }
+}
+
+/// 只读的文档不让保存，免得 U+FFFD 被写回去
+fn refuse_read_only(opened: Option<&OpenedFile>) -> Result<(), AppError> {
+    match opened {
+        Some(o) if o.read_only => {
+            eprintln!("Refusing to save: the file was opened with decode errors");
+            Err(AppError::Decode {
+                message: "Opened with decode errors, reopen lossy or with another encoding".into(),
+                offsets: o.decode_errors.clone(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 读文件并整理成给前端的数据，出错时不碰当前文档
+async fn read_document(
+    path: &str,
+    encode: &Encodes,
+    rules: &Rules,
+    lossy: bool,
+) -> anyhow::Result<(Data, OpenedFile)> {
+    let Loaded {
+        content,
+        mut format,
+        malformed,
+        revision,
+    } = read_with_encoding(path, encode).await?;
+    rules.apply(&mut format);
+
+    let encoding = format.encoding;
+    if *encode == Encodes::Auto {
+        println!("Detected encoding: {}", encoding.name());
+    }
+
+    let read_only = !malformed.is_empty() && !lossy;
+    if !malformed.is_empty() {
+        eprintln!(
+            "{} malformed byte sequence(s) in {} as {}, first at byte {}{}",
+            malformed.len(),
+            path,
+            encoding.name(),
+            malformed[0],
+            if read_only { ", opened read-only" } else { "" }
         );
     }
 
-    tokio::fs::write(path, &encoded_bytes).await?;
+    let decode_errors: Vec<usize> = malformed
+        .into_iter()
+        .take(MAX_REPORTED_DECODE_ERRORS)
+        .collect();
 
-    Ok(())
+    let data = Data {
+        content,
+        title: title_of(path),
+        saved: true,
+        encoding: Some(encoding.name().to_string()),
+        read_only,
+        decode_errors: decode_errors.clone(),
+        bom: Some(format.bom),
+        line_ending: Some(format.line_ending),
+        revision: Some(revision.clone()),
+        new_file: false,
+        indent: rules.indent.clone(),
+        total_lines: None,
+    };
+    let opened = OpenedFile {
+        format,
+        read_only,
+        lossy,
+        decode_errors,
+        revision: Some(revision),
+        content: Arc::from(data.content.as_str()),
+    };
+
+    Ok((data, opened))
 }
 
-async fn load(State(state): State<AppState>) -> Json<Data> {
-    let maybe_path = state.file_path.get();
-    let encode = state.encoding.get().clone().unwrap_or(&Encodes::Utf8);
-
-    match maybe_path {
-        Some(path) => {
-            match read_with_encoding(path, &encode).await {
-                Ok(content) => {
-                    let title = std::path::Path::new(path)
-                        .file_name()
-                        .map(|n| n.to_string_lossy().to_string())
-                        .unwrap_or_else(|| path.clone());
-
-                    Json(Data {
-                        content,
-                        title,
-                        saved: true,
-                    })
-                }
-                Err(e) => {
-                    // IO 失败处理：比如文件被占用或消失了
-                    eprintln!("Failed to read file {}: {}", path, e);
-                    Json(Data {
-                        content: format!("Error reading file: {}", e),
-                        title: "Error".into(),
-                        saved: false, // 既然读都读不到，肯定不能算 saved
-                    })
-                }
-            }
+/// 还没有文件的新文档：没有路径，或者路径上还没有文件，第一次保存时再创建
+fn blank(state: &AppState, document: &Document, rules: &Rules) -> Data {
+    let format = match &document.opened {
+        Some(o) => o.format.clone(),
+        None => FileFormat::new(state.encoding.resolve(None)),
+    };
+
+    Data {
+        content: state.template.as_deref().unwrap_or_default().to_string(),
+        title: document.title(),
+        saved: false,
+        encoding: Some(format.encoding.name().to_string()),
+        read_only: false,
+        decode_errors: Vec::new(),
+        bom: Some(format.bom),
+        line_ending: Some(format.line_ending),
+        revision: None,
+        new_file: document.path.is_some(),
+        indent: rules.indent.clone(),
+        total_lines: None,
+    }
+}
+
+/// 出错的话当成存在，让后面读文件时报出真正的错误
+async fn missing(path: &str) -> bool {
+    !tokio::fs::try_exists(path).await.unwrap_or(true)
+}
+
+async fn load(
+    State(state): State<AppState>,
+    Query(params): Query<LoadParams>,
+) -> Result<Json<Data>, AppError> {
+    let document = state.document.read().await.clone();
+    let opened = document.opened.as_ref();
+
+    let Some(path) = &document.path else {
+        // 初次打开
+        return Ok(Json(blank(&state, &document, &Rules::default())));
+    };
+
+    let rules = editorconfig::lookup(path).await;
+    let encode = encoding_for(&state, opened, &rules, params.encoding.as_deref())?;
+    let lossy = params.lossy || (params.encoding.is_none() && opened.is_some_and(|o| o.lossy));
+
+    if missing(path).await {
+        // 文件还不存在（或者被删了），当成新文件，保存时再创建
+        let mut document = state.document.write().await;
+        if document.path.as_ref() == Some(path)
+            && (params.encoding.is_some() || document.opened.is_none())
+        {
+            document.opened = Some(OpenedFile::new(new_format(encode, &rules)));
         }
+        return Ok(Json(blank(&state, &document, &rules)));
+    }
+
+    // IO 失败处理：比如文件被占用或消失了，报错就好，别把错误信息当成内容
+    let (data, opened) = read_document(path, &encode, &rules, lossy)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to read file {}: {:#}", path, e);
+            AppError::from(e)
+        })?;
+
+    let mut document = state.document.write().await;
+    // 读的过程中可能已经切换到别的文档了
+    if document.path.as_ref() == Some(path) {
+        document.opened = Some(opened);
+    }
+
+    Ok(Json(chunk(data, params.chunked)))
+}
+
+/// 前端要的话，太大的内容不放进 JSON，让它按 `/api/content/lines` 一段一段地取
+fn chunk(mut data: Data, chunked: bool) -> Data {
+    if chunked && data.content.len() > LARGE_DOCUMENT {
+        data.total_lines = Some(data.content.split('\n').count());
+        data.content = String::new();
+    }
+    data
+}
+
+/// 取文件的一段行，大文件一段一段地加载
+async fn lines(
+    State(state): State<AppState>,
+    Query(params): Query<LinesParams>,
+) -> Result<Json<Lines>, AppError> {
+    let document = state.document.read().await.clone();
+    let Some(path) = &document.path else {
+        return Err(AppError::NotFound(
+            "The document has not been saved yet".into(),
+        ));
+    };
+    // 磁盘上还是上次读 / 写的那个版本，就直接用记着的内容，不用每次都重新解码整个文件；
+    // 分段加载的后面几段带着版本号来，对得上连磁盘都不用读，磁盘上的新版本等文件监听通知
+    let remembered = document.opened.as_ref().and_then(|o| o.revision.clone());
+    let on_disk = if params.revision.is_some() && params.revision == remembered {
+        remembered
+    } else {
+        revision::of_file(std::path::Path::new(path))
+            .await
+            .ok()
+            .flatten()
+    };
+    let cached = document
+        .opened
+        .as_ref()
+        .filter(|o| params.encoding.is_none() && o.revision.is_some() && o.revision == on_disk);
+    let (content, encoding, revision) = match cached {
+        Some(opened) => (
+            opened.content.clone(),
+            opened.format.encoding,
+            opened.revision.clone().unwrap_or_default(),
+        ),
         None => {
-            // 初次打开
-            Json(Data {
-                content: String::new(),
-                title: DEFAULT_FILE_NAME.to_string(),
-                saved: false,
-            })
+            let rules = editorconfig::lookup(path).await;
+            let encode = encoding_for(
+                &state,
+                document.opened.as_ref(),
+                &rules,
+                params.encoding.as_deref(),
+            )?;
+            let loaded = read_with_encoding(path, &encode).await.map_err(|e| {
+                eprintln!("Failed to read file {}: {:#}", path, e);
+                AppError::from(e)
+            })?;
+            (
+                Arc::from(loaded.content),
+                loaded.format.encoding,
+                loaded.revision,
+            )
+        }
+    };
+
+    let count = params.count.unwrap_or(DEFAULT_LINES_COUNT);
+    let all = content.split('\n');
+    let total = all.clone().count();
+    let lines = all
+        .skip(params.start)
+        .take(count)
+        .map(str::to_string)
+        .collect();
+
+    Ok(Json(Lines {
+        title: title_of(path),
+        encoding: encoding.name().to_string(),
+        revision,
+        start: params.start,
+        total,
+        lines,
+    }))
+}
+
+/// 换一个文件来编辑
+async fn open(
+    State(state): State<AppState>,
+    params: Result<Json<OpenParams>, JsonRejection>,
+) -> Result<Json<Data>, AppError> {
+    let Json(params) = params?;
+
+    let path = match params.path {
+        Some(path) => client_path(&state, &path).await?,
+        None if state.headless => return Err(AppError::PathRequired),
+        None => match FileDialog::new()
+            .add_filter("Plaintext", &["txt"])
+            .add_filter("Markdown", &["md"])
+            .pick_file()
+        {
+            Some(path) => path.to_string_lossy().to_string(),
+            // 用户取消了对话框
+            None => return Err(AppError::Cancelled),
+        },
+    };
+
+    let rules = editorconfig::lookup(&path).await;
+    let encode = match &params.encoding {
+        Some(label) => parse_encoding(label)?,
+        None => default_encoding(&state, &rules),
+    };
+
+    if missing(&path).await {
+        let document = Document {
+            path: Some(path.clone()),
+            opened: Some(OpenedFile::new(new_format(encode, &rules))),
+        };
+        let data = blank(&state, &document, &rules);
+        switch_document(&state, document).await;
+        println!("New file {}, will be created on save", path);
+
+        return Ok(Json(data));
+    }
+
+    let (data, opened) = read_document(&path, &encode, &rules, params.lossy)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to read file {}: {:#}", path, e);
+            AppError::from(e)
+        })?;
+
+    switch_document(
+        &state,
+        Document {
+            path: Some(path.clone()),
+            opened: Some(opened),
+        },
+    )
+    .await;
+    println!("Opened {}", path);
+
+    Ok(Json(chunk(data, params.chunked)))
+}
+
+/// 新建一个还没有路径的空白文档，第一次保存时再选位置
+async fn new(
+    State(state): State<AppState>,
+    params: Result<Json<NewParams>, JsonRejection>,
+) -> Result<Json<Data>, AppError> {
+    let Json(params) = params?;
+    let document = match &params.encoding {
+        Some(label) => Document {
+            path: None,
+            opened: Some(OpenedFile::new(FileFormat::new(
+                parse_encoding(label)?.resolve(None),
+            ))),
+        },
+        None => Document::default(),
+    };
+
+    let data = blank(&state, &document, &Rules::default());
+    switch_document(&state, document).await;
+    println!("New untitled document");
+
+    Ok(Json(data))
+}
+
+async fn switch_document(state: &AppState, document: Document) {
+    let _guard = state.save_lock.lock().await;
+
+    let path = document.path.clone();
+    let title = document.title();
+    *state.document.write().await = document;
+
+    state.watched_path.send_replace(path);
+    let _ = state.events.send(FileEvent::Opened { title });
+}
+
+async fn save(
+    State(state): State<AppState>,
+    payload: Result<Json<Data>, JsonRejection>,
+) -> Result<Json<Data>, AppError> {
+    let Json(payload) = payload?;
+    let saved = store(
+        &state,
+        &payload.content,
+        SaveParams {
+            revision: payload.revision,
+            bom: payload.bom,
+            line_ending: payload.line_ending,
+        },
+    )
+    .await?;
+
+    Ok(Json(Data {
+        content: saved.content.unwrap_or(payload.content),
+        title: saved.title,
+        saved: true,
+        encoding: Some(saved.encoding),
+        read_only: false,
+        decode_errors: Vec::new(),
+        bom: Some(saved.bom),
+        line_ending: Some(saved.line_ending),
+        revision: Some(saved.revision),
+        new_file: false,
+        indent: saved.indent,
+        total_lines: None,
+    }))
+}
+
+/// 只发改动的部分：在后端记着的内容上改，改完整个写回去
+async fn patch_content(
+    State(state): State<AppState>,
+    params: Result<Json<PatchParams>, JsonRejection>,
+) -> Result<Json<Saved>, AppError> {
+    let Json(params) = params?;
+    let _guard = state.save_lock.lock().await;
+    let document = state.document.read().await.clone();
+
+    let base = base_content(&document, params.revision.as_deref()).await?;
+    let content =
+        patch::apply(&base, &params.edits).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let saved = store_locked(
+        &state,
+        &content,
+        SaveParams {
+            revision: params.revision,
+            bom: params.bom,
+            line_ending: params.line_ending,
+        },
+    )
+    .await?;
+
+    Ok(Json(saved))
+}
+
+/// 修改所基于的内容：一般就是后端记着的那份，对不上的话看看磁盘上的是不是那个版本
+async fn base_content(document: &Document, revision: Option<&str>) -> Result<Arc<str>, AppError> {
+    let opened = document.opened.as_ref();
+    if let Some(o) = opened
+        && revision.is_some()
+        && o.revision.as_deref() == revision
+    {
+        return Ok(o.content.clone());
+    }
+
+    let Some(path) = &document.path else {
+        return Err(AppError::conflict(
+            "The document has changed, save the whole content instead",
+            None,
+        ));
+    };
+    let encoding = opened
+        .map(|o| Encodes::Fixed(o.format.encoding))
+        .unwrap_or(Encodes::Auto);
+
+    match read_with_encoding(path, &encoding).await {
+        Ok(loaded) if revision == Some(loaded.revision.as_str()) => Ok(Arc::from(loaded.content)),
+        _ => {
+            eprintln!("Refusing to patch: {} has changed", path);
+            Err(conflict(path, opened, "The file has changed on disk").await)
+        }
+    }
+}
+
+/// 大文件直接 PUT 纯文本，不用包在 JSON 里：边收边写进临时文件，收完再替换原文件，也不把内容再传回去
+async fn save_raw(
+    State(state): State<AppState>,
+    Query(params): Query<SaveParams>,
+    body: Body,
+) -> Result<Json<Saved>, AppError> {
+    let _guard = state.save_lock.lock().await;
+    let target = save_target(&state, params).await?;
+    let (written, revision, reformatted) = stream_to_file(&state, &target, body).await?;
+
+    let mut saved = finish_save(&state, target, &written, revision).await;
+    saved.reload = reformatted;
+    Ok(Json(saved))
+}
+
+/// 请求体一块块转码写进临时文件，返回处理后的内容、新的版本号，以及有没有被 .editorconfig 改过
+async fn stream_to_file(
+    state: &AppState,
+    target: &SaveTarget,
+    body: Body,
+) -> Result<(String, String, bool), AppError> {
+    let path = std::path::Path::new(&target.path);
+    let fail = |e: anyhow::Error| {
+        eprintln!("Error writing file {}: {:#}", target.path, e);
+        AppError::from(e)
+    };
+    create_parent(path).await.map_err(fail)?;
+    let mut pending = atomic_write::Pending::create(path).await.map_err(fail)?;
+    let mut transcoder = streaming::Transcoder::new(&target.format, &target.rules);
+    let mut digest = revision::Hasher::default();
+
+    let mut received = 0;
+    let mut body = body.into_data_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk
+            .map_err(|e| AppError::BadRequest(format!("Failed to read the request body: {}", e)))?;
+        // DefaultBodyLimit 管不到直接读的 Body，自己数
+        received += chunk.len();
+        if state.max_body != 0 && received > state.max_body {
+            return Err(AppError::too_large());
+        }
+
+        transcoder.push(&chunk).map_err(fail)?;
+        if transcoder.buffered() >= STREAM_WRITE_SIZE {
+            let bytes = transcoder.take();
+            digest.update(&bytes);
+            pending = pending.write(bytes).await.map_err(fail)?;
         }
     }
+    let finished = transcoder.finish().map_err(fail)?;
+    digest.update(&finished.bytes);
+    pending = pending.write(finished.bytes).await.map_err(fail)?;
+
+    // 收完了才备份、替换，中途失败原文件不动
+    backup::backup(path, &state.backup)
+        .await
+        .map_err(|e| fail(e.context("Backup failed, file left untouched")))?;
+    pending.commit().await.map_err(fail)?;
+    let meta = tokio::fs::metadata(path)
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    Ok((
+        finished.content,
+        revision::with_digest(&digest.finish(), &meta),
+        finished.reformatted,
+    ))
 }
 
-async fn save(State(state): State<AppState>, Json(payload): axum::Json<Data>) -> Json<Data> {
-    let current_path = if let Some(path) = state.file_path.get() {
+async fn store(state: &AppState, content: &str, payload: SaveParams) -> Result<Saved, AppError> {
+    let _guard = state.save_lock.lock().await;
+    store_locked(state, content, payload).await
+}
+
+/// 保存前对版本用；读不了文件就别保存，不然可能盖掉一个只是暂时读不了的文件
+async fn disk_revision(path: &str) -> Result<Option<String>, AppError> {
+    revision::of_file(std::path::Path::new(path))
+        .await
+        .map_err(|e| {
+            eprintln!("Refusing to save: cannot read {}: {:#}", path, e);
+            AppError::from(e)
+        })
+}
+
+/// 调用前要拿着 save_lock
+async fn store_locked(
+    state: &AppState,
+    content: &str,
+    payload: SaveParams,
+) -> Result<Saved, AppError> {
+    let target = save_target(state, payload).await?;
+    let written = target.rules.apply_content(content);
+    let revision = write_file(state, &target.path, &written, &target.format)
+        .await
+        .map_err(|e| {
+            eprintln!("Error writing file {}: {:#}", target.path, e);
+            AppError::from(e)
+        })?;
+
+    let mut saved = finish_save(state, target, &written, revision).await;
+    if let Cow::Owned(content) = written {
+        saved.content = Some(content);
+    }
+    Ok(saved)
+}
+
+/// 保存前定下来的：写到哪、用什么格式
+struct SaveTarget {
+    /// 保存前的文档
+    document: Document,
+    path: String,
+    rules: Rules,
+    format: FileFormat,
+    /// 文件还不存在，这次保存会新建
+    created: bool,
+}
+
+/// 对一下版本号，定下保存的路径和格式；调用前要拿着 save_lock
+async fn save_target(state: &AppState, payload: SaveParams) -> Result<SaveTarget, AppError> {
+    let document = state.document.read().await.clone();
+    let opened = document.opened.as_ref();
+
+    refuse_read_only(opened)?;
+
+    let current_path = if let Some(path) = &document.path {
+        // 对一下版本号，文件在别处被改过就不能直接覆盖
+        if let Some(on_disk) = disk_revision(path).await?
+            && payload.revision.as_deref() != Some(on_disk.as_str())
+        {
+            eprintln!("Refusing to save: {} has changed on disk", path);
+            return Err(conflict(path, opened, "The file has changed on disk").await);
+        }
+
+        path.clone()
+    } else if state.headless {
+        let Some(path) = &state.default_path else {
+            return Err(AppError::PathRequired);
+        };
+
+        // 默认路径上已经有文件了，同样不能直接覆盖
+        if let Some(on_disk) = disk_revision(path).await?
+            && payload.revision.as_deref() != Some(on_disk.as_str())
+        {
+            eprintln!("Refusing to save: {} already exists", path);
+            return Err(conflict(path, None, "The file already exists").await);
+        }
+
+        println!("New file has saved at {}", path);
         path.clone()
     } else {
         if let Some(path) = FileDialog::new()
@@ -139,46 +1012,456 @@ async fn save(State(state): State<AppState>, Json(payload): axum::Json<Data>) ->
         {
             let path_str = path.to_string_lossy().to_string();
 
-            let final_path = state
-                .file_path
-                .get_or_init(|| async { path_str.clone() })
-                .await;
-
-            println!("New file has saved at {}", final_path);
-            final_path.clone()
+            println!("New file has saved at {}", path_str);
+            path_str
         } else {
             // 用户取消了对话框
-            return Json(Data {
-                content: payload.content,
-                title: payload.title,
-                saved: false,
-            });
+            return Err(AppError::Cancelled);
         }
     };
 
-    let encoding = state.encoding.get().unwrap_or(&Encodes::Utf8);
+    let rules = editorconfig::lookup(&current_path).await;
+    let mut format = match opened {
+        Some(o) => o.format.clone(),
+        None => FileFormat::new(state.encoding.resolve(None)),
+    };
+    if document.path.is_none() {
+        // 第一次有了位置，按那里的 .editorconfig 来
+        if let Some(encoding) = rules.encoding() {
+            format.encoding = encoding;
+        }
+        rules.apply(&mut format);
+    }
 
-    let save_res = write_with_encoding(&current_path, &payload.content, encoding).await;
+    // 前端可以改 BOM 和换行符
+    if let Some(bom) = payload.bom {
+        format.bom = bom;
+    }
+    if let Some(line_ending) = payload.line_ending
+        && line_ending != format.line_ending
+    {
+        format.line_ending = line_ending;
+        format.line_breaks = Arc::new([]);
+    }
+
+    let created = missing(&current_path).await;
+    Ok(SaveTarget {
+        document,
+        path: current_path,
+        rules,
+        format,
+        created,
+    })
+}
 
-    let title = std::path::Path::new(&current_path)
-        .file_name()
-        .map(|n| n.to_string_lossy().to_string())
-        .unwrap_or(DEFAULT_FILE_NAME.into());
+/// 写完以后记下新内容、通知别的标签页、记字数
+async fn finish_save(
+    state: &AppState,
+    target: SaveTarget,
+    written: &str,
+    revision: String,
+) -> Saved {
+    let SaveTarget {
+        document,
+        path: current_path,
+        rules,
+        format,
+        created,
+    } = target;
+    let opened = document.opened;
 
-    match save_res {
-        Ok(_) => Json(Data {
-            content: payload.content,
-            title,
+    *state.document.write().await = Document {
+        path: Some(current_path.clone()),
+        opened: Some(OpenedFile {
+            format: format.clone(),
+            read_only: false,
+            lossy: opened.as_ref().is_some_and(|o| o.lossy),
+            decode_errors: Vec::new(),
+            revision: Some(revision.clone()),
+            content: Arc::from(written),
+        }),
+    };
+    // 新建的文件所在目录可能刚刚才建出来，重新监听一下
+    if document.path.is_none() || created {
+        state.watched_path.send_replace(Some(current_path.clone()));
+    }
+    if created {
+        println!("Created {}", current_path);
+    }
+    let _ = state.events.send(FileEvent::Saved {
+        revision: revision.clone(),
+    });
+    let before = opened.as_ref().map_or("", |o| &o.content);
+    record_progress(state, &current_path, before, written).await;
+
+    Saved {
+        title: title_of(&current_path),
+        encoding: format.encoding.name().to_string(),
+        bom: format.bom,
+        line_ending: format.line_ending,
+        revision,
+        indent: rules.indent,
+        content: None,
+        reload: false,
+    }
+}
+
+/// 新文件的上级目录可能还不存在
+async fn create_parent(path: &std::path::Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// 备份之后写入，返回新的版本号
+async fn write_file(
+    state: &AppState,
+    path: &str,
+    content: &str,
+    format: &FileFormat,
+) -> anyhow::Result<String> {
+    create_parent(std::path::Path::new(path)).await?;
+
+    // 备份失败就别往下写了，宁可这次保存不成功
+    match backup::backup(std::path::Path::new(path), &state.backup).await {
+        Ok(_) => write_with_encoding(path, content, format).await,
+        Err(e) => Err(e.context("Backup failed, file left untouched")),
+    }
+}
+
+/// 把当前内容存到另一个路径，可以换编码；默认之后就编辑新文件，`copy` 时只存一份副本
+async fn save_as(
+    State(state): State<AppState>,
+    params: Result<Json<SaveAsParams>, JsonRejection>,
+) -> Result<Json<Data>, AppError> {
+    let Json(params) = params?;
+    let _guard = state.save_lock.lock().await;
+    let document = state.document.read().await.clone();
+    let opened = document.opened.clone();
+
+    refuse_read_only(opened.as_ref())?;
+
+    let target_encoding = params.encoding.as_deref().map(parse_encoding).transpose()?;
+
+    let (path, overwrite) = match params.path {
+        Some(path) => (client_path(&state, &path).await?, params.overwrite),
+        None if state.headless => return Err(AppError::PathRequired),
+        None => {
+            let mut dialog = FileDialog::new()
+                .add_filter("Plaintext", &["txt"])
+                .add_filter("Markdown", &["md"])
+                .set_file_name(document.title());
+            if let Some(dir) = document
+                .path
+                .as_deref()
+                .and_then(|p| std::path::Path::new(p).parent())
+                .filter(|dir| !dir.as_os_str().is_empty())
+            {
+                dialog = dialog.set_directory(dir);
+            }
+
+            match dialog.save_file() {
+                // 对话框已经问过要不要覆盖了
+                Some(path) => (path.to_string_lossy().to_string(), true),
+                // 用户取消了对话框
+                None => return Err(AppError::Cancelled),
+            }
+        }
+    };
+
+    if !overwrite && tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Err(AppError::conflict(format!("{} already exists", path), None));
+    }
+
+    // 新位置的 .editorconfig 先来，前端明确要的编码、BOM、换行符再盖上去
+    let rules = editorconfig::lookup(&path).await;
+    let mut format = match &opened {
+        Some(o) => o.format.clone(),
+        None => FileFormat::new(state.encoding.resolve(None)),
+    };
+    if let Some(encoding) = rules.encoding() {
+        format.encoding = encoding;
+    }
+    rules.apply(&mut format);
+
+    if let Some(target) = target_encoding {
+        // auto 在这里就是保持原来的编码
+        let encoding = target.resolve(Some(format.encoding));
+        if encoding != format.encoding {
+            format.encoding = encoding;
+            // 新编码没有 BOM 的话就别带了
+            format.bom &= !encoding::bom(encoding).is_empty();
+        }
+    }
+    if let Some(bom) = params.bom {
+        format.bom = bom;
+    }
+    if let Some(line_ending) = params.line_ending
+        && line_ending != format.line_ending
+    {
+        format.line_ending = line_ending;
+        format.line_breaks = Arc::new([]);
+    }
+
+    let content = rules.apply_content(&params.content).into_owned();
+    let revision = write_file(&state, &path, &content, &format)
+        .await
+        .map_err(|e| {
+            eprintln!("Error writing file {}: {:#}", path, e);
+            AppError::from(e)
+        })?;
+    if !params.copy {
+        // 换了个地方接着写，从原来的内容算起
+        let before = opened.as_ref().map_or("", |o| &o.content);
+        record_progress(&state, &path, before, &content).await;
+    }
+
+    let data = Data {
+        content,
+        title: title_of(&path),
+        saved: true,
+        encoding: Some(format.encoding.name().to_string()),
+        read_only: false,
+        decode_errors: Vec::new(),
+        bom: Some(format.bom),
+        line_ending: Some(format.line_ending),
+        revision: Some(revision.clone()),
+        new_file: false,
+        indent: rules.indent.clone(),
+        total_lines: None,
+    };
+
+    if params.copy {
+        println!("Saved a copy at {}", path);
+    } else {
+        // 已经拿着 save_lock 了，不能走 switch_document
+        *state.document.write().await = Document {
+            path: Some(path.clone()),
+            opened: Some(OpenedFile {
+                format,
+                read_only: false,
+                lossy: opened.is_some_and(|o| o.lossy),
+                decode_errors: Vec::new(),
+                revision: Some(revision),
+                content: Arc::from(data.content.as_str()),
+            }),
+        };
+        state.watched_path.send_replace(Some(path.clone()));
+        let _ = state.events.send(FileEvent::Opened {
+            title: data.title.clone(),
+        });
+        println!("Saved as {}", path);
+    }
+
+    Ok(Json(data))
+}
+
+/// 记不下来也不影响保存
+async fn record_progress(state: &AppState, path: &str, before: &str, after: &str) {
+    let (before, after) = (stats::count(before), stats::count(after));
+    if let Err(e) = state.progress.record(path, &before, &after).await {
+        eprintln!("Failed to record writing progress: {:#}", e);
+    }
+}
+
+/// 409，顺便把磁盘上现在的内容和版本号带回去让前端决定怎么办
+async fn conflict(path: &str, opened: Option<&OpenedFile>, message: &str) -> AppError {
+    let encoding = opened
+        .map(|o| Encodes::Fixed(o.format.encoding))
+        .unwrap_or(Encodes::Auto);
+
+    let current = match read_with_encoding(path, &encoding).await {
+        Ok(loaded) => Some(Data {
+            content: loaded.content,
+            title: title_of(path),
             saved: true,
+            encoding: Some(loaded.format.encoding.name().to_string()),
+            read_only: !loaded.malformed.is_empty() && !opened.is_some_and(|o| o.lossy),
+            decode_errors: loaded
+                .malformed
+                .into_iter()
+                .take(MAX_REPORTED_DECODE_ERRORS)
+                .collect(),
+            bom: Some(loaded.format.bom),
+            line_ending: Some(loaded.format.line_ending),
+            revision: Some(loaded.revision),
+            new_file: false,
+            indent: editorconfig::lookup(path).await.indent,
+            total_lines: None,
         }),
         Err(e) => {
-            eprintln!("Error writing file: {}", e);
-            Json(Data {
-                content: payload.content,
-                title,
-                saved: false,
-            })
+            eprintln!("Failed to read file {}: {}", path, e);
+            None
+        }
+    };
+
+    AppError::conflict(message, current)
+}
+
+/// 列出 --save-dir 下的目录，给前端的路径选择框用
+async fn list_dir(
+    State(state): State<AppState>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<browse::Listing>, AppError> {
+    let Some(root) = &state.save_dir else {
+        return Err(AppError::NotFound("No --save-dir to browse".into()));
+    };
+
+    Ok(Json(browse::list(root, &params.path).await?))
+}
+
+async fn settings(State(state): State<AppState>) -> Json<config::Editor> {
+    Json(state.editor.as_ref().clone())
+}
+
+/// 已保存的内容，还没加载过就直接读文件，文件还不存在就是空的
+async fn saved_content(state: &AppState, document: &Document) -> Result<Arc<str>, AppError> {
+    if let Some(opened) = &document.opened {
+        return Ok(opened.content.clone());
+    }
+    let Some(path) = document.path.as_deref() else {
+        return Ok(Arc::from(""));
+    };
+    if missing(path).await {
+        return Ok(Arc::from(""));
+    }
+
+    let rules = editorconfig::lookup(path).await;
+    let loaded = read_with_encoding(path, &default_encoding(state, &rules))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to read file {}: {:#}", path, e);
+            AppError::from(e)
+        })?;
+    Ok(Arc::from(loaded.content))
+}
+
+/// 已保存的内容的字数统计
+async fn stats(State(state): State<AppState>) -> Result<Json<stats::Stats>, AppError> {
+    let document = state.document.read().await.clone();
+    let content = saved_content(&state, &document).await?;
+    Ok(Json(stats::count(&content)))
+}
+
+/// 把已保存的内容导出成 .docx 下载，`.md` 文件按 Markdown 转换
+async fn export_docx(State(state): State<AppState>) -> Result<Response, AppError> {
+    let document = state.document.read().await.clone();
+    let Some(path) = document.path.as_deref() else {
+        return Err(AppError::NotFound(
+            "The document has not been saved yet".into(),
+        ));
+    };
+    let content = saved_content(&state, &document).await?;
+
+    let path = std::path::Path::new(path);
+    let markdown = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"));
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| DEFAULT_FILE_NAME.to_string());
+
+    let bytes = docx::build(&name, &content, markdown)?;
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(docx::CONTENT_TYPE),
+        ),
+        (
+            header::CONTENT_DISPOSITION,
+            attachment(&format!("{}.docx", name)),
+        ),
+    ];
+    Ok((headers, bytes).into_response())
+}
+
+/// 文件名可能是中文，ASCII 的 filename 给老浏览器兜底，filename* 才是真名
+fn attachment(file_name: &str) -> HeaderValue {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    ))
+    .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+/// 每天写了多少、连续写了几天，以及当前文档离目标还差多少
+async fn progress(
+    State(state): State<AppState>,
+    Query(params): Query<ProgressParams>,
+) -> Json<progress::Report> {
+    let document = state.document.read().await.clone();
+    let current = document.path.as_deref().map(|path| {
+        (
+            path,
+            stats::count(document.opened.as_ref().map_or("", |o| &o.content)),
+        )
+    });
+    let days = params
+        .days
+        .unwrap_or(DEFAULT_PROGRESS_DAYS)
+        .clamp(1, MAX_PROGRESS_DAYS);
+    let goals = goals_for(&state, document.path.as_deref()).await;
+    Json(
+        state
+            .progress
+            .report(
+                current.as_ref().map(|(path, stats)| (*path, stats)),
+                goals,
+                days,
+            )
+            .await,
+    )
+}
+
+/// 命令行没给的目标从文档所在目录往上的配置里读，每次都重新读，切换文档或改了配置都跟着变
+async fn goals_for(state: &AppState, path: Option<&str>) -> progress::Goals {
+    let goals = state.goals;
+    if goals.daily.is_some() && goals.target.is_some() {
+        return goals;
+    }
+
+    let path = path.map(str::to_string);
+    let config = tokio::task::spawn_blocking(move || config::load(path.as_deref())).await;
+    let config = match config {
+        Ok(Ok(config)) => config,
+        Ok(Err(e)) => {
+            eprintln!("Warning: {:#}", e);
+            return goals;
         }
+        Err(_) => return goals,
+    };
+    let get = |key: &str| {
+        config.get(key).unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}", e);
+            None
+        })
+    };
+    progress::Goals {
+        daily: goals.daily.or_else(|| get("daily-goal")),
+        target: goals.target.or_else(|| get("target")),
     }
 }
 
@@ -186,52 +1469,618 @@ async fn status() -> StatusCode {
     StatusCode::OK
 }
 
+async fn events(
+    State(state): State<AppState>,
+) -> Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    events::stream(
+        state.events.subscribe(),
+        state.shutdown.subscribe(),
+        shutdown::Client::connect(&state.clients),
+    )
+}
+
+/// 监听当前文档的文件变化并推给所有 SSE 连接，文档换了就跟着换
+fn spawn_watcher(state: AppState) {
+    let mut paths = state.watched_path.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            let path = paths.borrow_and_update().clone();
+            let watched = path.and_then(|path| match watcher::watch(std::path::Path::new(&path)) {
+                Ok((watcher, changes)) => Some((path, watcher, changes)),
+                Err(e) => {
+                    // 新文件的目录还没建出来很正常，第一次保存之后会重新监听
+                    if std::path::absolute(&path)
+                        .ok()
+                        .and_then(|p| p.parent().map(|p| p.exists()))
+                        .unwrap_or(true)
+                    {
+                        eprintln!("Failed to watch file {}: {}", path, e);
+                    }
+                    None
+                }
+            });
+
+            let Some((path, _watcher, mut changes)) = watched else {
+                if paths.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+
+            loop {
+                let change = tokio::select! {
+                    changed = paths.changed() => match changed {
+                        Ok(()) => break,
+                        Err(_) => return,
+                    },
+                    change = changes.recv() => match change {
+                        Some(change) => change,
+                        None => break,
+                    },
+                };
+
+                if let Some(event) = file_event(&state, &path, change).await {
+                    println!("File {}: {:?}", path, event);
+                    let _ = state.events.send(event);
+                }
+            }
+        }
+    });
+}
+
+async fn file_event(state: &AppState, path: &str, change: watcher::Change) -> Option<FileEvent> {
+    match change {
+        watcher::Change::Modified => {
+            // 等正在进行的保存写完再比对，自己保存引起的变化不用通知
+            let _guard = state.save_lock.lock().await;
+            let on_disk = revision::of_file(std::path::Path::new(path))
+                .await
+                .ok()
+                .flatten();
+
+            let document = state.document.read().await;
+            if document.path.as_deref() != Some(path) {
+                return None;
+            }
+            let known = document.opened.as_ref().and_then(|o| o.revision.clone());
+
+            if on_disk.is_none() || on_disk == known {
+                return None;
+            }
+            Some(FileEvent::Modified { revision: on_disk })
+        }
+        watcher::Change::Removed => Some(FileEvent::Removed),
+        watcher::Change::Renamed(to) => Some(FileEvent::Renamed {
+            to: to.map(|p| p.display().to_string()),
+        }),
+    }
+}
+
+/// 文件存在就看能不能打开来写；不存在就看最近一层存在的上级目录里能不能建文件
+fn check_writable(path: &std::path::Path) -> anyhow::Result<()> {
+    if path.exists() {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("{} is not writable", path.display()))?;
+        return Ok(());
+    }
+
+    let dir = path
+        .ancestors()
+        .skip(1)
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                p
+            }
+        })
+        .find(|p| p.exists())
+        .unwrap_or(std::path::Path::new("."));
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    tempfile::tempfile_in(dir)
+        .with_context(|| format!("Cannot create files in {}", dir.display()))?;
+
+    Ok(())
+}
+
+/// Linux 上没有图形界面（SSH、容器里）时 rfd 的对话框弹不出来
+fn no_display() -> bool {
+    cfg!(all(unix, not(target_os = "macos")))
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+/// 端口被占了就往后试这么多个
+const PORT_ATTEMPTS: u16 = 20;
+
+/// 所有地址绑在同一个端口上，被占了就换下一个端口；`port` 为 0 时让系统挑
+async fn bind(hosts: &[IpAddr], port: u16) -> std::io::Result<Vec<(SocketAddr, TcpListener)>> {
+    let mut in_use = None;
+    for offset in 0..PORT_ATTEMPTS {
+        let Some(candidate) = port.checked_add(if port == 0 { 0 } else { offset }) else {
+            break;
+        };
+        match bind_all(hosts, candidate) {
+            Ok(listeners) => return Ok(listeners),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => in_use = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(in_use.unwrap_or_else(|| std::io::ErrorKind::AddrInUse.into()))
+}
+
+/// 所有地址用同一个端口；只有第一个地址绑不上才算端口被占了，换下一个端口试
+fn bind_all(hosts: &[IpAddr], port: u16) -> std::io::Result<Vec<(SocketAddr, TcpListener)>> {
+    // Linux 上 `::` 默认同时收 IPv4，和 0.0.0.0 一起监听会撞端口
+    let v6_only = hosts.iter().any(IpAddr::is_ipv4);
+    let mut port = port;
+    let mut listeners = Vec::new();
+    for ip in hosts {
+        let addr = SocketAddr::new(*ip, port);
+        let listener = match listen(addr, v6_only) {
+            Ok(listener) => listener,
+            Err(e) if listeners.is_empty() => {
+                return Err(std::io::Error::new(e.kind(), format!("{}: {}", addr, e)));
+            }
+            // 前面的地址已经绑上了，这不是端口被占满的问题，原样报出来
+            Err(e) => return Err(std::io::Error::other(format!("{}: {}", addr, e))),
+        };
+        let addr = listener.local_addr()?;
+        // 系统挑的端口，后面的地址也用这个
+        port = addr.port();
+        listeners.push((addr, listener));
+    }
+    Ok(listeners)
+}
+
+fn listen(addr: SocketAddr, v6_only: bool) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    // 和 TcpListener::bind 一样，Windows 上 SO_REUSEADDR 意思不同，不能设
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// 双击打开时出错窗口会直接关掉，停一下让人看到报错
+fn exit_with_pause(no_pause: bool) -> ! {
+    if !no_pause && std::io::stdin().is_terminal() {
+        press_btn_continue::wait("Press any key to continue...").unwrap();
+    }
+    std::process::exit(1);
+}
+
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    // 先解析一遍拿到文档路径，才知道该读哪个目录的配置
+    let config = match config::load(Args::parse().path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let command = match config.apply(Args::command()) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let matches = command.get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // 配置文件里的目标只管它那个目录，换了文档得重新读，这里只留命令行和环境变量给的
+    let explicit = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    let goals = progress::Goals {
+        daily: args.daily_goal.filter(|_| explicit("daily_goal")),
+        target: args.target.filter(|_| explicit("target")),
+    };
 
-    // 还得可选初始化，没东西就别碰 OnceCell
-    let file_path = Arc::new(OnceCell::new());
-    if let Some(p) = args.path {
-        let _ = file_path.set(p);
+    if let Some(path) = &args.path {
+        let path = std::path::Path::new(path);
+        if path.is_dir() {
+            eprintln!("Error: {} is a directory", path.display());
+            std::process::exit(1);
+        }
+        // 只是提醒，只读的文件也能打开来看
+        if let Err(e) = check_writable(path) {
+            eprintln!("Warning: {:#}, saving will fail", e);
+        }
     }
 
-    let encoding = Arc::new(OnceCell::new());
-    let _ = encoding.set(args.encoding);
+    let template = match &args.template {
+        Some(path) => match read_with_encoding(&path.to_string_lossy(), &Encodes::Auto).await {
+            Ok(loaded) => Some(Arc::from(loaded.content)),
+            Err(e) => {
+                eprintln!("Error: Cannot read the template: {:#}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let headless = args.headless || no_display();
+    let save_dir = match args
+        .save_dir
+        .or_else(|| headless.then(|| PathBuf::from(".")))
+        .map(std::fs::canonicalize)
+        .transpose()
+    {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: Cannot use the save directory: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let default_path = match (&save_dir, args.default_path) {
+        (Some(root), Some(path)) => match browse::resolve(root, &path).await {
+            Ok(path) => Some(path.to_string_lossy().to_string()),
+            Err(e) => {
+                eprintln!("Error: Cannot use the default path: {}", e);
+                std::process::exit(1);
+            }
+        },
+        (None, path) => path,
+        (_, None) => None,
+    };
+
+    let document = Document {
+        path: args.path.clone(),
+        opened: None,
+    };
 
     let state = AppState {
-        file_path,
-        encoding,
+        document: Arc::new(RwLock::new(document)),
+        encoding: args.encoding,
+        backup: Arc::new(BackupPolicy {
+            mode: args.backup,
+            dir: args.backup_dir,
+            keep: args.backup_keep,
+            max_age: args.backup_max_age.map(backup::days),
+        }),
+        save_lock: Arc::new(Mutex::new(())),
+        events: broadcast::channel(16).0,
+        watched_path: watch::Sender::new(args.path),
+        headless,
+        save_dir: save_dir.clone().map(Arc::new),
+        default_path,
+        template,
+        editor: Arc::new(config.editor),
+        clients: watch::Sender::new(0),
+        shutdown: watch::Sender::new(false),
+        progress: Arc::new(progress::Store::open().await),
+        max_body: args.max_body,
+        goals,
+    };
+
+    // 空密码当成没设
+    let password = args.password.filter(|p| !p.is_empty());
+    if let Some(ip) = args.hosts.iter().find(|ip| !ip.is_loopback())
+        && password.is_none()
+    {
+        eprintln!(
+            "Error: Listening on {} exposes the document to the network, set a password with --password or SIMPLY_WRITER_PASSWORD",
+            ip
+        );
+        std::process::exit(1);
+    }
+
+    let guard = match Guard::new(password, &args.tls_names) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
     };
 
+    spawn_watcher(state.clone());
+
+    let clients = state.clients.subscribe();
+    let exit_on_close = args.exit_on_close;
+    let stop = state.shutdown.clone();
+    tokio::spawn(async move {
+        shutdown::wait(clients, exit_on_close).await;
+        println!("Shutting down...");
+        stop.send_replace(true);
+    });
+    let stopping = state.shutdown.subscribe();
+
     let app = Router::new()
         .route("/api/status", get(status))
-        .route("/api/content", get(load).post(save))
+        .route("/api/settings", get(settings))
+        .route("/api/stats", get(stats))
+        .route("/api/progress", get(progress))
+        .route("/api/export/docx", get(export_docx))
+        .route("/api/content", get(load).post(save).patch(patch_content))
+        .route("/api/content/raw", put(save_raw))
+        .route("/api/content/lines", get(lines))
+        .route("/api/events", get(events))
+        .route("/api/open", post(open))
+        .route("/api/new", post(new))
+        .route("/api/save-as", post(save_as))
+        .route("/api/fs/list", get(list_dir))
         .route("/", get(|| async { Html(INDEX_HTML) }))
+        .layer(match args.max_body {
+            0 => DefaultBodyLimit::disable(),
+            limit => DefaultBodyLimit::max(limit),
+        })
+        .layer(middleware::from_fn_with_state(guard.clone(), guard::check))
         .with_state(state);
 
-    println!("Encoding: {:?}", args.encoding);
+    for file in &config.files {
+        println!("Config: {}", file.display());
+    }
+    println!("Encoding: {}", args.encoding);
+    if headless {
+        println!("Headless mode, no file dialogs");
+    }
+    if let Some(dir) = &save_dir {
+        println!("Save directory: {}", dir.display());
+    }
 
-    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], args.port));
-    println!("Service run at: http://{}", addr);
+    let tls = if args.tls || args.tls_cert.is_some() {
+        // 只编译了 ring 一套算法，得先告诉 rustls 用它
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        match tls::config(
+            args.tls_cert.as_deref(),
+            args.tls_key.as_deref(),
+            &args.hosts,
+            &args.tls_names,
+        )
+        .await
+        {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
 
-    let listener = match tokio::net::TcpListener::bind(&addr).await {
-        Ok(l) => l,
+    let listeners = match bind(&args.hosts, args.port).await {
+        Ok(listeners) => listeners,
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::AddrInUse {
+            if e.kind() == std::io::ErrorKind::AddrInUse && args.port != 0 {
                 eprintln!(
-                    "Error: Address {} has already been used. Please use another available port.",
-                    addr
+                    "Error: Ports {}-{} have all been used. Please use another available port.",
+                    args.port,
+                    args.port.saturating_add(PORT_ATTEMPTS - 1)
                 );
             } else {
-                eprintln!("Address binding error ({}): {}", addr, e);
+                eprintln!("Address binding error ({})", e);
             }
 
-            press_btn_continue::wait("Press any key to continue...").unwrap();
-
-            std::process::exit(1);
+            exit_with_pause(args.no_pause);
         }
     };
+    if let Some((addr, _)) = listeners.first()
+        && args.port != 0
+        && addr.port() != args.port
+    {
+        println!(
+            "Port {} is in use, using {} instead",
+            args.port,
+            addr.port()
+        );
+    }
+
+    let mut servers = tokio::task::JoinSet::new();
+    for (addr, listener) in listeners {
+        // 令牌跟在地址后面，直接点开就能用
+        if addr.ip().is_unspecified() {
+            let local = match addr {
+                SocketAddr::V4(_) => SocketAddr::from(([127, 0, 0, 1], addr.port())),
+                SocketAddr::V6(_) => SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], addr.port())),
+            };
+            println!(
+                "Service run at: {}://{}/?token={}",
+                scheme,
+                local,
+                guard.token()
+            );
+            println!(
+                "Listening on all interfaces ({}), use this machine's IP address from other devices",
+                addr
+            );
+        } else {
+            println!(
+                "Service run at: {}://{}/?token={}",
+                scheme,
+                addr,
+                guard.token()
+            );
+        }
+
+        // 不再接新连接，等手上的请求（比如正在写的保存）做完再退
+        let app = app.clone();
+        let mut stopping = stopping.clone();
+        match &tls {
+            Some(config) => {
+                let handle = axum_server::Handle::new();
+                let server =
+                    axum_server::from_tcp_rustls(listener.into_std().unwrap(), config.clone())
+                        .handle(handle.clone());
+                tokio::spawn(async move {
+                    let _ = stopping.wait_for(|stop| *stop).await;
+                    handle.graceful_shutdown(None);
+                });
+                servers.spawn(async move { server.serve(app.into_make_service()).await });
+            }
+            None => {
+                servers.spawn(async move {
+                    axum::serve(listener, app)
+                        .with_graceful_shutdown(async move {
+                            let _ = stopping.wait_for(|stop| *stop).await;
+                        })
+                        .await
+                });
+            }
+        }
+    }
 
-    axum::serve(listener, app).await.unwrap();
+    while let Some(result) = servers.join_next().await {
+        result.unwrap().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binds_ipv4_and_ipv6_wildcards_together() {
+        // 没有 IPv6 的机器上测不了
+        if std::net::TcpListener::bind("[::]:0").is_err() {
+            return;
+        }
+        let hosts: Vec<IpAddr> = vec!["0.0.0.0".parse().unwrap(), "::".parse().unwrap()];
+        let listeners = bind(&hosts, 0).await.unwrap();
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[0].0.port(), listeners[1].0.port());
+    }
+
+    #[tokio::test]
+    async fn unreadable_file_blocks_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.txt");
+        assert_eq!(
+            disk_revision(missing.to_str().unwrap()).await.unwrap(),
+            None
+        );
+        // 目录读不了，当成暂时读不了的文件
+        assert!(disk_revision(dir.path().to_str().unwrap()).await.is_err());
+
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "hi").unwrap();
+        assert!(
+            disk_revision(file.to_str().unwrap())
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    /// 编辑 `path` 的后端，不备份、不弹对话框
+    fn state_for(path: &std::path::Path) -> AppState {
+        let path = path.to_string_lossy().to_string();
+        AppState {
+            document: Arc::new(RwLock::new(Document {
+                path: Some(path.clone()),
+                opened: None,
+            })),
+            encoding: Encodes::Auto,
+            backup: Arc::new(BackupPolicy {
+                mode: BackupMode::None,
+                dir: PathBuf::new(),
+                keep: 0,
+                max_age: None,
+            }),
+            save_lock: Arc::new(Mutex::new(())),
+            events: broadcast::channel(16).0,
+            watched_path: watch::Sender::new(Some(path)),
+            headless: true,
+            save_dir: None,
+            default_path: None,
+            template: None,
+            editor: Arc::new(config::Editor::default()),
+            clients: watch::Sender::new(0),
+            shutdown: watch::Sender::new(false),
+            progress: Arc::new(progress::Store::in_memory()),
+            max_body: 0,
+            goals: progress::Goals::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn patch_and_put_keep_crlf() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "one\r\ntwo\r\nthree\r\n").unwrap();
+        let state = state_for(&file);
+
+        let Json(data) = load(State(state.clone()), Query(LoadParams::default()))
+            .await
+            .unwrap();
+        assert_eq!(data.content, "one\ntwo\nthree\n");
+        assert_eq!(data.line_ending, Some(LineEnding::Crlf));
+
+        let Json(saved) = patch_content(
+            State(state.clone()),
+            Ok(Json(PatchParams {
+                revision: data.revision,
+                edits: vec![patch::Edit {
+                    offset: 4,
+                    length: 3,
+                    replacement: "2\n2.5".into(),
+                }],
+                bom: None,
+                line_ending: None,
+            })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.line_ending, LineEnding::Crlf);
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "one\r\n2\r\n2.5\r\nthree\r\n"
+        );
+
+        // 别的客户端 PUT 过来的带着 \r\n 也一样
+        let Json(saved) = save_raw(
+            State(state.clone()),
+            Query(SaveParams {
+                revision: Some(saved.revision),
+                ..SaveParams::default()
+            }),
+            Body::from("put\r\nlines\nhere\n"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.line_ending, LineEnding::Crlf);
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "put\r\nlines\r\nhere\r\n"
+        );
+        let document = state.document.read().await;
+        let opened = document.opened.as_ref().unwrap();
+        assert_eq!(&*opened.content, "put\nlines\nhere\n");
+        assert_eq!(opened.revision.as_ref(), Some(&saved.revision));
+    }
+
+    #[tokio::test]
+    async fn later_address_conflict_is_not_reported_as_port_in_use() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let free = std::net::TcpListener::bind("127.0.0.2:0");
+        // 127.0.0.2 不一定能用，比如 macOS 上
+        if free.is_err() {
+            return;
+        }
+        drop(free);
+
+        let hosts: Vec<IpAddr> = vec!["127.0.0.2".parse().unwrap(), "127.0.0.1".parse().unwrap()];
+        let e = bind_all(&hosts, port).unwrap_err();
+        assert_ne!(e.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(e.to_string().contains("127.0.0.1"));
+    }
 }
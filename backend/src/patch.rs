@@ -0,0 +1,145 @@
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+/// 一处修改：把 `offset` 开始的 `length` 个字符换成 `replacement`
+///
+/// 偏移和长度都按 UTF-16 码元算，和前端 JS 字符串的下标一致
+#[derive(Deserialize, Debug, Clone)]
+pub struct Edit {
+    pub offset: usize,
+    pub length: usize,
+    pub replacement: String,
+}
+
+/// 把一组修改应用到 `text` 上
+///
+/// 偏移都相对于修改前的 `text`，修改之间不能重叠，顺序无所谓
+pub fn apply(text: &str, edits: &[Edit]) -> Result<String> {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|e| e.offset);
+
+    for pair in edits.windows(2) {
+        if pair[0].offset.saturating_add(pair[0].length) > pair[1].offset {
+            bail!("Edits at {} and {} overlap", pair[0].offset, pair[1].offset);
+        }
+    }
+
+    // 所有用到的 UTF-16 位置，按顺序一遍换算成字节偏移
+    let positions: Vec<usize> = edits
+        .iter()
+        .flat_map(|e| [e.offset, e.offset.saturating_add(e.length)])
+        .collect();
+    let bytes = byte_offsets(text, &positions)?;
+
+    let inserted: usize = edits.iter().map(|e| e.replacement.len()).sum();
+    let mut patched = String::with_capacity(text.len() + inserted);
+    let mut copied = 0;
+    for (edit, range) in edits.iter().zip(bytes.chunks(2)) {
+        patched.push_str(&text[copied..range[0]]);
+        patched.push_str(&edit.replacement);
+        copied = range[1];
+    }
+    patched.push_str(&text[copied..]);
+
+    Ok(patched)
+}
+
+/// `positions` 要从小到大排好
+fn byte_offsets(text: &str, positions: &[usize]) -> Result<Vec<usize>> {
+    let mut bytes = Vec::with_capacity(positions.len());
+    let mut positions = positions.iter().peekable();
+    let mut utf16 = 0;
+
+    for (byte, c) in text.char_indices() {
+        while let Some(&&position) = positions.peek() {
+            if position > utf16 {
+                break;
+            }
+            if position < utf16 {
+                bail!("Offset {} splits a surrogate pair", position);
+            }
+            bytes.push(byte);
+            positions.next();
+        }
+        utf16 += c.len_utf16();
+    }
+
+    for &position in positions {
+        if position < utf16 {
+            bail!("Offset {} splits a surrogate pair", position);
+        }
+        if position > utf16 {
+            bail!(
+                "Offset {} is past the end of the text ({})",
+                position,
+                utf16
+            );
+        }
+        bytes.push(text.len());
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(offset: usize, length: usize, replacement: &str) -> Edit {
+        Edit {
+            offset,
+            length,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn offsets_are_utf16_units() {
+        // 中文一个字一个码元，emoji 两个
+        assert_eq!(
+            apply("你好😀世界", &[edit(4, 1, "，")]).unwrap(),
+            "你好😀，界"
+        );
+        assert_eq!(apply("😀a", &[edit(2, 1, "b")]).unwrap(), "😀b");
+        assert_eq!(apply("a😀", &[edit(1, 2, "")]).unwrap(), "a");
+    }
+
+    #[test]
+    fn edits_apply_against_the_original_text() {
+        // 顺序打乱也一样，偏移都按原文算
+        let edits = [edit(6, 5, "there"), edit(0, 0, ">> "), edit(5, 1, ", ")];
+        assert_eq!(apply("hello world", &edits).unwrap(), ">> hello, there");
+        assert_eq!(apply("abc", &[]).unwrap(), "abc");
+        assert_eq!(apply("abc", &[edit(3, 0, "d")]).unwrap(), "abcd");
+    }
+
+    #[test]
+    fn adjacent_edits_are_fine() {
+        assert_eq!(
+            apply("abcd", &[edit(0, 2, "X"), edit(2, 2, "Y")]).unwrap(),
+            "XY"
+        );
+    }
+
+    #[test]
+    fn overlapping_edits_are_rejected() {
+        assert!(apply("abcdef", &[edit(0, 3, "x"), edit(2, 2, "y")]).is_err());
+        assert!(apply("abcdef", &[edit(1, 4, "x"), edit(2, 0, "y")]).is_err());
+        // 长度大到溢出也不能当成不重叠
+        assert!(apply("abcdef", &[edit(1, usize::MAX, "x"), edit(3, 1, "y")]).is_err());
+    }
+
+    #[test]
+    fn surrogate_pairs_cannot_be_split() {
+        assert!(apply("a😀b", &[edit(2, 0, "x")]).is_err());
+        assert!(apply("a😀b", &[edit(0, 2, "")]).is_err());
+        assert!(apply("😀", &[edit(1, 1, "")]).is_err());
+    }
+
+    #[test]
+    fn offsets_past_the_end_are_rejected() {
+        assert!(apply("abc", &[edit(4, 0, "x")]).is_err());
+        assert!(apply("abc", &[edit(2, 2, "")]).is_err());
+        assert!(apply("你好", &[edit(0, usize::MAX, "")]).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{Days, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{atomic_write, stats::Stats};
+
+const FILE_NAME: &str = "progress.json";
+/// 这么多天以前的记录只留每天最后一条，够算每天写了多少就行
+const DETAIL_DAYS: u64 = 7;
+
+/// 每日目标和这篇文档的目标字数，都是可选的
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct Goals {
+    pub daily: Option<usize>,
+    pub target: Option<usize>,
+}
+
+/// 某次保存后文档的字数
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct Sample {
+    /// Unix 时间戳，秒
+    time: i64,
+    /// 保存时的本地日期，换了时区以前的记录也不会挪到别的天
+    date: NaiveDate,
+    words: usize,
+    characters: usize,
+}
+
+impl Sample {
+    fn now(stats: &Stats) -> Self {
+        let now = Local::now();
+        Sample {
+            time: now.timestamp(),
+            date: now.date_naive(),
+            words: stats.words,
+            characters: stats.characters_no_spaces,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Data {
+    /// 按绝对路径记
+    files: BTreeMap<String, Vec<Sample>>,
+}
+
+/// 一天净写了多少，删得比写得多就是负的
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub words: i64,
+    pub characters: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DocumentProgress {
+    pub words: usize,
+    pub characters: usize,
+    /// 今天在这篇文档里写了多少
+    pub today: Day,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub goals: Goals,
+    pub today: Day,
+    /// 最近几天，从早到晚，没写的天是 0
+    pub days: Vec<Day>,
+    /// 连续写作的天数，设了每日目标就是连续达标的天数；今天还没写不算断
+    pub streak: usize,
+    pub longest_streak: usize,
+    pub document: Option<DocumentProgress>,
+}
+
+/// 每次保存后的字数都记在本地的一个 JSON 文件里
+pub struct Store {
+    /// 没有数据目录就只记在内存里
+    path: Option<PathBuf>,
+    data: Mutex<Data>,
+}
+
+impl Store {
+    /// 放在数据目录的 `simply-writer/progress.json`，读不了就从头记
+    pub async fn open() -> Self {
+        let path = dirs::data_dir().map(|dir| dir.join("simply-writer").join(FILE_NAME));
+        let data = match &path {
+            Some(path) => match tokio::fs::read(path).await {
+                Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    eprintln!("Ignoring broken {}: {}", path.display(), e);
+                    Data::default()
+                }),
+                Err(_) => Data::default(),
+            },
+            None => Data::default(),
+        };
+        Store {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    /// 只记在内存里
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Store {
+            path: None,
+            data: Mutex::new(Data::default()),
+        }
+    }
+
+    /// 保存成功后记一笔；`before` 是保存前的内容，第一次记这个文件时当作起点
+    pub async fn record(&self, file: &str, before: &Stats, after: &Stats) -> Result<()> {
+        let key = key(file);
+        let sample = Sample::now(after);
+
+        let mut data = self.data.lock().await;
+        let samples = data.files.entry(key).or_default();
+        match samples.last() {
+            None => samples.push(Sample::now(before)),
+            // 没改字数的保存不用记
+            Some(last)
+                if last.date == sample.date
+                    && last.words == sample.words
+                    && last.characters == sample.characters =>
+            {
+                return Ok(());
+            }
+            Some(_) => {}
+        }
+        samples.push(sample);
+        compact(samples, sample.date);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // 拿着锁写，两次保存的记录不会互相覆盖
+        atomic_write::write(path, serde_json::to_vec(&*data)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// 所有文件加起来每天写了多少，以及 `document`（路径和现在的字数）的进度
+    pub async fn report(
+        &self,
+        document: Option<(&str, &Stats)>,
+        goals: Goals,
+        days: usize,
+    ) -> Report {
+        let data = self.data.lock().await;
+        build_report(&data, document, goals, days, Local::now().date_naive())
+    }
+}
+
+/// `report` 去掉了锁和“今天”，方便按指定的日期算
+fn build_report(
+    data: &Data,
+    document: Option<(&str, &Stats)>,
+    goals: Goals,
+    days: usize,
+    today: NaiveDate,
+) -> Report {
+    let mut totals: BTreeMap<NaiveDate, (i64, i64)> = BTreeMap::new();
+    for samples in data.files.values() {
+        for (date, written) in written_per_day(samples) {
+            let total = totals.entry(date).or_default();
+            total.0 += written.0;
+            total.1 += written.1;
+        }
+    }
+    let day = |date: NaiveDate| {
+        let (words, characters) = totals.get(&date).copied().unwrap_or_default();
+        Day {
+            date,
+            words,
+            characters,
+        }
+    };
+
+    let met = |date: &NaiveDate| {
+        totals
+            .get(date)
+            .is_some_and(|&(words, _)| match goals.daily {
+                Some(goal) => words >= goal as i64,
+                None => words > 0,
+            })
+    };
+    let mut streak = 0;
+    let mut date = if met(&today) {
+        Some(today)
+    } else {
+        today.pred_opt()
+    };
+    while let Some(d) = date.filter(met) {
+        streak += 1;
+        date = d.pred_opt();
+    }
+    let mut longest_streak = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for date in totals.keys().filter(|d| met(d)) {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(*date) => run + 1,
+            _ => 1,
+        };
+        longest_streak = usize::max(longest_streak, run);
+        previous = Some(*date);
+    }
+
+    let document = document.map(|(file, stats)| {
+        let (words, characters) = data
+            .files
+            .get(&key(file))
+            .map(|samples| written_per_day(samples))
+            .and_then(|written| written.get(&today).copied())
+            .unwrap_or_default();
+        DocumentProgress {
+            words: stats.words,
+            characters: stats.characters_no_spaces,
+            today: Day {
+                date: today,
+                words,
+                characters,
+            },
+        }
+    });
+
+    let first = today
+        .checked_sub_days(Days::new(days.saturating_sub(1) as u64))
+        .unwrap_or(today);
+    Report {
+        goals,
+        today: day(today),
+        days: first.iter_days().take(days).map(day).collect(),
+        streak,
+        longest_streak,
+        document,
+    }
+}
+
+fn key(file: &str) -> String {
+    std::path::absolute(file)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| file.to_string())
+}
+
+/// 每天的字数变化加起来，第一条记录是起点
+fn written_per_day(samples: &[Sample]) -> BTreeMap<NaiveDate, (i64, i64)> {
+    let mut written = BTreeMap::new();
+    let Some(first) = samples.first() else {
+        return written;
+    };
+    let mut previous = first;
+    for sample in samples {
+        let day: &mut (i64, i64) = written.entry(sample.date).or_default();
+        day.0 += sample.words as i64 - previous.words as i64;
+        day.1 += sample.characters as i64 - previous.characters as i64;
+        previous = sample;
+    }
+    written
+}
+
+/// 老记录每天只留最后一条，起点那条也留着
+fn compact(samples: &mut Vec<Sample>, today: NaiveDate) {
+    let Some(cutoff) = today.checked_sub_days(Days::new(DETAIL_DAYS)) else {
+        return;
+    };
+    let mut kept: Vec<Sample> = Vec::with_capacity(samples.len());
+    for (i, sample) in samples.iter().enumerate() {
+        let replaces_previous = i > 1
+            && sample.date < cutoff
+            && kept.last().is_some_and(|last| last.date == sample.date);
+        if replaces_previous {
+            kept.pop();
+        }
+        kept.push(*sample);
+    }
+    *samples = kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn sample(day: u32, words: usize) -> Sample {
+        Sample {
+            time: 0,
+            date: date(day),
+            words,
+            characters: words * 2,
+        }
+    }
+
+    fn data(files: &[(&str, Vec<Sample>)]) -> Data {
+        Data {
+            files: files
+                .iter()
+                .map(|(file, samples)| (key(file), samples.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn written_per_day_starts_from_the_first_sample() {
+        let samples = [
+            sample(1, 100),
+            sample(1, 150),
+            sample(1, 130),
+            sample(2, 200),
+            // 删得比写得多
+            sample(4, 50),
+        ];
+        let written = written_per_day(&samples);
+        assert_eq!(written[&date(1)], (30, 60));
+        assert_eq!(written[&date(2)], (70, 140));
+        assert_eq!(written[&date(4)], (-150, -300));
+        assert!(!written.contains_key(&date(3)));
+        assert!(written_per_day(&[]).is_empty());
+    }
+
+    #[test]
+    fn compact_keeps_the_last_sample_of_old_days() {
+        let mut samples = vec![
+            sample(1, 100),
+            sample(1, 110),
+            sample(1, 120),
+            sample(2, 130),
+            sample(2, 140),
+            sample(20, 150),
+            sample(20, 160),
+        ];
+        let before = written_per_day(&samples);
+        compact(&mut samples, date(20));
+
+        let words: Vec<usize> = samples.iter().map(|s| s.words).collect();
+        // 起点留着，老的每天只留最后一条，最近几天的都留着
+        assert_eq!(words, [100, 120, 140, 150, 160]);
+        assert_eq!(written_per_day(&samples), before);
+    }
+
+    #[test]
+    fn streak_is_broken_by_a_missing_day() {
+        let data = data(&[(
+            "a.txt",
+            vec![
+                sample(1, 0),
+                sample(2, 10),
+                sample(3, 20),
+                sample(4, 30),
+                // 5 号没写
+                sample(6, 40),
+                sample(7, 50),
+            ],
+        )]);
+        let report = build_report(&data, None, Goals::default(), 7, date(7));
+        assert_eq!(report.streak, 2);
+        assert_eq!(report.longest_streak, 3);
+        // 从早到晚，没写的天是 0
+        let words: Vec<i64> = report.days.iter().map(|d| d.words).collect();
+        assert_eq!(words, [0, 10, 10, 10, 0, 10, 10]);
+        assert_eq!(report.days[0].date, date(1));
+
+        // 今天还没写不算断
+        let report = build_report(&data, None, Goals::default(), 7, date(8));
+        assert_eq!(report.streak, 2);
+        assert_eq!(report.today.words, 0);
+        // 昨天也没写就断了
+        let report = build_report(&data, None, Goals::default(), 7, date(9));
+        assert_eq!(report.streak, 0);
+        assert_eq!(report.longest_streak, 3);
+    }
+
+    #[test]
+    fn streak_counts_days_that_met_the_goal() {
+        let data = data(&[(
+            "a.txt",
+            vec![
+                sample(1, 0),
+                sample(2, 500),
+                sample(3, 700),
+                sample(4, 1300),
+            ],
+        )]);
+        let goals = Goals {
+            daily: Some(500),
+            target: None,
+        };
+        let report = build_report(&data, None, goals, 7, date(4));
+        // 3 号只写了 200
+        assert_eq!(report.streak, 1);
+        assert_eq!(report.longest_streak, 1);
+    }
+
+    #[test]
+    fn deleting_is_negative_and_does_not_count_for_streaks() {
+        let data = data(&[("a.txt", vec![sample(1, 0), sample(2, 100), sample(3, 40)])]);
+        let report = build_report(&data, None, Goals::default(), 3, date(3));
+        assert_eq!(report.today.words, -60);
+        assert_eq!(report.today.characters, -120);
+        assert_eq!(report.streak, 1);
+    }
+
+    #[test]
+    fn today_adds_up_all_files_and_the_document_only_its_own() {
+        let data = data(&[
+            ("a.txt", vec![sample(1, 0), sample(2, 100), sample(2, 130)]),
+            ("b.txt", vec![sample(1, 0), sample(1, 20), sample(2, 50)]),
+        ]);
+        let stats = Stats {
+            words: 130,
+            characters_no_spaces: 260,
+            ..Stats::default()
+        };
+        let report = build_report(&data, Some(("a.txt", &stats)), Goals::default(), 2, date(2));
+        assert_eq!(report.today.words, 160);
+        let document = report.document.unwrap();
+        assert_eq!(document.words, 130);
+        assert_eq!(document.characters, 260);
+        // 昨天写的不算进今天
+        assert_eq!(document.today.words, 130);
+
+        let report = build_report(&data, Some(("b.txt", &stats)), Goals::default(), 2, date(2));
+        assert_eq!(report.document.unwrap().today.words, 30);
+        assert_eq!(report.days[0].words, 20);
+    }
+}
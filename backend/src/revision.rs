@@ -0,0 +1,55 @@
+use std::{fs::Metadata, path::Path, time::UNIX_EPOCH};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+/// 文件在磁盘上的版本号：mtime、大小加内容哈希，保存前比对一下防止覆盖别人的修改
+pub fn compute(bytes: &[u8], meta: &Metadata) -> String {
+    with_digest(&digest(bytes), meta)
+}
+
+/// 内容哈希的前 64 位，写文件前先算好，省得写完再读一遍
+pub fn digest(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+/// 边写边算的 `digest`
+#[derive(Default)]
+pub struct Hasher(Sha256);
+
+impl Hasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub fn finish(self) -> String {
+        hex(&self.0.finalize())
+    }
+}
+
+fn hex(hash: &[u8]) -> String {
+    hash[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn with_digest(digest: &str, meta: &Metadata) -> String {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    format!("{:x}-{:x}-{}", mtime, meta.len(), digest)
+}
+
+/// 文件不存在时返回 None
+pub async fn of_file(path: &Path) -> Result<Option<String>> {
+    let bytes = match tokio::fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let meta = tokio::fs::metadata(path).await?;
+
+    Ok(Some(compute(&bytes, &meta)))
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// 最后一个标签页断开以后等这么久，刷新页面会断开再马上重连
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// 一个连着的标签页（SSE 连接），连接断了 stream 被丢掉时计数减一
+pub struct Client(watch::Sender<usize>);
+
+impl Client {
+    pub fn connect(clients: &watch::Sender<usize>) -> Self {
+        clients.send_modify(|n| *n += 1);
+        Client(clients.clone())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.0.send_modify(|n| *n -= 1);
+    }
+}
+
+/// 等到该退出的时候：Ctrl+C、SIGTERM、Windows 上关控制台窗口，或者 `exit_on_close` 时最后一个标签页关掉
+pub async fn wait(clients: watch::Receiver<usize>, exit_on_close: bool) {
+    let closed = async {
+        if exit_on_close {
+            last_client_gone(clients).await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate() => {}
+        _ = closed => println!("All pages are closed"),
+    }
+}
+
+async fn last_client_gone(mut clients: watch::Receiver<usize>) {
+    // 还没有人连上来就别退，可能浏览器还没打开
+    if clients.wait_for(|n| *n > 0).await.is_err() {
+        return;
+    }
+    loop {
+        if clients.wait_for(|n| *n == 0).await.is_err() {
+            return;
+        }
+        tokio::time::sleep(CLOSE_GRACE).await;
+        if *clients.borrow_and_update() == 0 {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            term.recv().await;
+        }
+        Err(_) => std::future::pending().await,
+    }
+}
+
+/// 关掉控制台窗口、注销、关机；系统只给几秒钟收尾，超时就直接杀掉进程
+#[cfg(windows)]
+async fn terminate() {
+    use tokio::signal::windows::{ctrl_close, ctrl_logoff, ctrl_shutdown};
+
+    // 注册不上的那个就当它永远不来
+    macro_rules! event {
+        ($listen:expr) => {
+            async {
+                match $listen {
+                    Ok(mut event) => {
+                        event.recv().await;
+                    }
+                    Err(_) => std::future::pending().await,
+                }
+            }
+        };
+    }
+
+    tokio::select! {
+        _ = event!(ctrl_close()) => {}
+        _ = event!(ctrl_shutdown()) => {}
+        _ = event!(ctrl_logoff()) => {}
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn terminate() {
+    std::future::pending().await
+}
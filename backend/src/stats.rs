@@ -0,0 +1,271 @@
+use serde::Serialize;
+
+/// 中文大约每分钟读 400 字
+const CJK_PER_MINUTE: usize = 400;
+/// 英文大约每分钟读 200 词
+const WORDS_PER_MINUTE: usize = 200;
+
+/// 文档的字数统计，口径和 Word 的“字数统计”一致
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 字数：每个汉字、假名、全角标点算一个，英文等按词算，韩文按空格分开的词算
+    pub words: usize,
+    /// 字符数（计空格），不算换行
+    pub characters: usize,
+    /// 字符数（不计空格）
+    pub characters_no_spaces: usize,
+    /// 字符数（不计空格和标点）
+    pub characters_no_punctuation: usize,
+    /// 汉字、假名和韩文字母，不含标点
+    pub cjk_characters: usize,
+    /// 非中文单词
+    pub latin_words: usize,
+    /// 有内容的段落，空行不算
+    pub paragraphs: usize,
+    pub lines: usize,
+    /// 向上取整，空文档是 0
+    pub reading_minutes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    /// 汉字、假名，一个字算一个
+    Cjk,
+    /// 韩文按词算
+    Hangul,
+    /// 中文标点和全角字符，一个算一个
+    Wide,
+    /// 弯引号、破折号、省略号这些中英文都用的，挨着英文就算进单词里
+    Ambiguous,
+    Latin,
+}
+
+/// 内容里的换行已经统一成 `\n`；前端 `frontend-web/src/stats.ts` 有一份一样的，改的时候一起改
+pub fn count(content: &str) -> Stats {
+    let mut stats = Stats::default();
+    // 正在数的英文 / 韩文单词
+    let mut run: Option<Class> = None;
+    let mut last = Class::Space;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            run = None;
+            last = Class::Space;
+            continue;
+        }
+
+        stats.characters += 1;
+        let class = classify(c);
+        if class != Class::Space {
+            stats.characters_no_spaces += 1;
+            if !is_punctuation(c) {
+                stats.characters_no_punctuation += 1;
+            }
+        }
+
+        match class {
+            Class::Space => run = None,
+            Class::Cjk | Class::Wide => {
+                run = None;
+                stats.words += 1;
+                if class == Class::Cjk {
+                    stats.cjk_characters += 1;
+                }
+            }
+            Class::Ambiguous => {
+                if run.is_none() {
+                    // 前面不是中文、后面紧跟着英文，比如 “Hello”，就是英文单词的开头
+                    if !matches!(last, Class::Cjk | Class::Wide)
+                        && chars
+                            .peek()
+                            .is_some_and(|&next| classify(next) == Class::Latin)
+                    {
+                        run = Some(Class::Latin);
+                        stats.latin_words += 1;
+                    }
+                    stats.words += 1;
+                }
+            }
+            Class::Hangul | Class::Latin => {
+                if class == Class::Hangul {
+                    stats.cjk_characters += 1;
+                }
+                if run != Some(class) {
+                    run = Some(class);
+                    stats.words += 1;
+                    if class == Class::Latin {
+                        stats.latin_words += 1;
+                    }
+                }
+            }
+        }
+        last = class;
+    }
+
+    if !content.is_empty() {
+        // 结尾的换行后面不算一行
+        stats.lines = content
+            .strip_suffix('\n')
+            .unwrap_or(content)
+            .split('\n')
+            .count();
+    }
+    stats.paragraphs = content
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .count();
+    stats.reading_minutes = (stats.cjk_characters * WORDS_PER_MINUTE
+        + stats.latin_words * CJK_PER_MINUTE)
+        .div_ceil(CJK_PER_MINUTE * WORDS_PER_MINUTE);
+
+    stats
+}
+
+fn classify(c: char) -> Class {
+    match c {
+        _ if c.is_whitespace() => Class::Space,
+        // 谚文字母、兼容字母、音节
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+            Class::Hangul
+        }
+        '\u{30FB}' => Class::Wide,
+        // 汉字（含扩展区、兼容区）、假名、注音；々 〆 〇 也当汉字
+        '\u{3005}'..='\u{3007}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3100}'..='\u{312F}'
+        | '\u{31A0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{323AF}' => Class::Cjk,
+        // 中文标点、竖排和小写变体、全角字符
+        '\u{3000}'..='\u{303F}'
+        | '\u{FE10}'..='\u{FE1F}'
+        | '\u{FE30}'..='\u{FE6F}'
+        | '\u{FF01}'..='\u{FF65}'
+        | '\u{FFE0}'..='\u{FFEE}' => Class::Wide,
+        '\u{2010}'..='\u{205E}' => Class::Ambiguous,
+        _ => Class::Latin,
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '¡' | '§' | '«' | '¶' | '·' | '»' | '¿'
+                | '\u{2010}'..='\u{205E}'
+                | '\u{3001}'..='\u{3003}'
+                | '\u{3008}'..='\u{3011}'
+                | '\u{3014}'..='\u{301F}'
+                | '\u{30FB}'
+                | '\u{FE10}'..='\u{FE1F}'
+                | '\u{FE30}'..='\u{FE6F}'
+                | '\u{FF01}'..='\u{FF0F}'
+                | '\u{FF1A}'..='\u{FF20}'
+                | '\u{FF3B}'..='\u{FF40}'
+                | '\u{FF5B}'..='\u{FF65}'
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(count(""), Stats::default());
+    }
+
+    #[test]
+    fn cjk_counts_each_character() {
+        let stats = count("你好世界");
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.cjk_characters, 4);
+        assert_eq!(stats.latin_words, 0);
+        assert_eq!(stats.characters, 4);
+
+        // 假名、々 也是一个一个算
+        assert_eq!(count("ひらがなカタカナ").words, 8);
+        assert_eq!(count("人々").words, 2);
+    }
+
+    #[test]
+    fn latin_counts_words() {
+        let stats = count("Hello, world! It's fine.");
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.latin_words, 4);
+        assert_eq!(stats.characters, 24);
+        assert_eq!(stats.characters_no_spaces, 21);
+        assert_eq!(stats.characters_no_punctuation, 17);
+        // 连字符、破折号连着的算一个词
+        assert_eq!(count("well-known word—word").words, 2);
+    }
+
+    #[test]
+    fn hangul_counts_words() {
+        let stats = count("안녕하세요 세계");
+        assert_eq!(stats.words, 2);
+        assert_eq!(stats.cjk_characters, 7);
+        assert_eq!(stats.latin_words, 0);
+    }
+
+    #[test]
+    fn digits_are_words() {
+        assert_eq!(count("3.14 and 1,000").words, 3);
+        // 数字和后面的汉字分开算
+        let stats = count("2024年");
+        assert_eq!(stats.words, 2);
+        assert_eq!(stats.latin_words, 1);
+        assert_eq!(stats.cjk_characters, 1);
+        // 全角数字一个算一个
+        assert_eq!(count("２０２４").words, 4);
+    }
+
+    #[test]
+    fn punctuation() {
+        // 中文标点一个算一个字
+        let stats = count("你好，世界。");
+        assert_eq!(stats.words, 6);
+        assert_eq!(stats.characters_no_spaces, 6);
+        assert_eq!(stats.characters_no_punctuation, 4);
+
+        // 弯引号挨着英文算进单词里，挨着中文就单独算
+        assert_eq!(count("“Hello”").words, 1);
+        assert_eq!(count("他说：“你好”").words, 7);
+        // 前后有空格的英文标点自己也算一个词，Word 也是这样
+        assert_eq!(count("a - b").words, 3);
+    }
+
+    #[test]
+    fn mixed() {
+        let stats = count("我用Rust写代码，version 2.0。");
+        // 我 用 Rust 写 代 码 ， version 2.0 。
+        assert_eq!(stats.words, 10);
+        assert_eq!(stats.cjk_characters, 5);
+        assert_eq!(stats.latin_words, 3);
+    }
+
+    #[test]
+    fn lines_and_paragraphs() {
+        let stats = count("第一段\n\n  \nsecond paragraph\n");
+        assert_eq!(stats.lines, 4);
+        assert_eq!(stats.paragraphs, 2);
+        // 换行不算字符，空格算
+        assert_eq!(stats.characters, 3 + 2 + 16);
+        assert_eq!(count("no newline").lines, 1);
+    }
+
+    #[test]
+    fn reading_minutes_round_up() {
+        assert_eq!(count(&"字".repeat(400)).reading_minutes, 1);
+        assert_eq!(count(&"字".repeat(401)).reading_minutes, 2);
+        assert_eq!(count(&"word ".repeat(200)).reading_minutes, 1);
+        // 200 字 + 100 词，各占半分钟
+        let mixed = format!("{}{}", "字".repeat(200), " word".repeat(100));
+        assert_eq!(count(&mixed).reading_minutes, 1);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{fmt, sync::Arc};
+
+use anyhow::Result;
+use encoding_rs::{EncoderResult, Encoding, UTF_16BE, UTF_16LE};
+
+use crate::{
+    document::FileFormat,
+    editorconfig::Rules,
+    encoding::{self, Unencodable},
+    line_ending::{self, LineEnding},
+};
+
+/// 请求体不是 UTF-8
+#[derive(Debug)]
+pub struct NotUtf8;
+
+impl fmt::Display for NotUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The request body is not valid UTF-8")
+    }
+}
+
+impl std::error::Error for NotUtf8 {}
+
+/// 请求体收完后剩下的
+pub struct Finished {
+    /// 最后一点要写进文件的字节
+    pub bytes: Vec<u8>,
+    /// 处理后的完整内容，`\n` 换行
+    pub content: String,
+    /// .editorconfig 改过内容，和发来的不一样了
+    pub reformatted: bool,
+}
+
+/// 把一块块收到的 UTF-8 正文转成要写进文件的字节，结果和整篇保存时一样：
+/// 换行统一成 `\n`，按 .editorconfig 去掉行尾空白、处理结尾换行，再还原换行符、编码
+///
+/// 处理后的内容（`\n` 换行）另外留一份，保存完后端要记着
+pub struct Transcoder {
+    /// 上一块结尾没收完的 UTF-8 字节
+    partial: Vec<u8>,
+    /// 上一个字符是 `\r`，紧跟着的 `\n` 不再算一次换行
+    after_cr: bool,
+
+    trim: bool,
+    final_newline: Option<bool>,
+    /// 可能是行尾空白，看到后面还有字才写出去
+    spaces: String,
+    /// 不要结尾换行时，可能在结尾的换行先攒着
+    newlines: usize,
+    reformatted: bool,
+
+    /// 处理后的内容
+    content: String,
+    /// `content` 里还没编码的部分从哪开始
+    encoded_to: usize,
+    /// 已经编码了几处换行，换行符混用时按这个找原来的换行符
+    line: usize,
+    line_ending: LineEnding,
+    line_breaks: Arc<[LineEnding]>,
+    fallback: LineEnding,
+
+    encoding: &'static Encoding,
+    encoder: encoding_rs::Encoder,
+    out: Vec<u8>,
+}
+
+impl Transcoder {
+    pub fn new(format: &FileFormat, rules: &Rules) -> Self {
+        let mut out = Vec::new();
+        if format.bom {
+            out.extend_from_slice(encoding::bom(format.encoding));
+        }
+        Transcoder {
+            partial: Vec::new(),
+            after_cr: false,
+            trim: rules.trim_trailing_whitespace == Some(true),
+            final_newline: rules.insert_final_newline,
+            spaces: String::new(),
+            newlines: 0,
+            reformatted: false,
+            content: String::new(),
+            encoded_to: 0,
+            line: 0,
+            line_ending: format.line_ending,
+            line_breaks: format.line_breaks.clone(),
+            fallback: line_ending::most_common(&format.line_breaks),
+            encoding: format.encoding,
+            encoder: format.encoding.new_encoder(),
+            out,
+        }
+    }
+
+    /// 收到一块请求体
+    pub fn push(&mut self, bytes: &[u8]) -> Result<()> {
+        let mut bytes = bytes;
+        let joined;
+        if !self.partial.is_empty() {
+            self.partial.extend_from_slice(bytes);
+            joined = std::mem::take(&mut self.partial);
+            bytes = &joined;
+        }
+
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            // 结尾的字符被切开了，留到下一块
+            Err(e) if e.error_len().is_none() => {
+                let (text, rest) = bytes.split_at(e.valid_up_to());
+                self.partial = rest.to_vec();
+                std::str::from_utf8(text)?
+            }
+            Err(_) => return Err(NotUtf8.into()),
+        };
+        for c in text.chars() {
+            self.char(c);
+        }
+        self.encode(false)
+    }
+
+    /// 已经转好、可以写进文件的字节
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out)
+    }
+
+    /// 攒着还没取走的字节数
+    pub fn buffered(&self) -> usize {
+        self.out.len()
+    }
+
+    /// 请求体收完了
+    pub fn finish(mut self) -> Result<Finished> {
+        if !self.partial.is_empty() {
+            return Err(NotUtf8.into());
+        }
+        self.drop_spaces();
+        match self.final_newline {
+            Some(true) if !self.content.is_empty() && !self.content.ends_with('\n') => {
+                self.content.push('\n');
+                self.reformatted = true;
+            }
+            Some(false) if self.newlines > 0 => {
+                self.newlines = 0;
+                self.reformatted = true;
+            }
+            _ => {}
+        }
+        self.encode(true)?;
+        Ok(Finished {
+            bytes: self.out,
+            content: self.content,
+            reformatted: self.reformatted,
+        })
+    }
+
+    fn drop_spaces(&mut self) {
+        if !self.spaces.is_empty() {
+            self.spaces.clear();
+            self.reformatted = true;
+        }
+    }
+
+    fn char(&mut self, c: char) {
+        let c = match c {
+            '\n' if self.after_cr => {
+                self.after_cr = false;
+                return;
+            }
+            '\r' => {
+                self.after_cr = true;
+                '\n'
+            }
+            c => {
+                self.after_cr = false;
+                c
+            }
+        };
+
+        match c {
+            '\n' => {
+                // 行尾的空白不要了
+                self.drop_spaces();
+                if self.final_newline == Some(false) {
+                    self.newlines += 1;
+                } else {
+                    self.content.push('\n');
+                }
+            }
+            ' ' | '\t' if self.trim => self.spaces.push(c),
+            c => {
+                self.content
+                    .extend(std::iter::repeat_n('\n', self.newlines));
+                self.newlines = 0;
+                self.content.push_str(&self.spaces);
+                self.spaces.clear();
+                self.content.push(c);
+            }
+        }
+    }
+
+    /// 把新处理好的内容还原换行符、编码
+    fn encode(&mut self, last: bool) -> Result<()> {
+        let fresh = &self.content[self.encoded_to..];
+        let mut restored = String::with_capacity(fresh.len());
+        for (i, piece) in fresh.split('\n').enumerate() {
+            if i > 0 {
+                let ending = match self.line_ending {
+                    LineEnding::Mixed => *self.line_breaks.get(self.line).unwrap_or(&self.fallback),
+                    ending => ending,
+                };
+                self.line += 1;
+                restored.push_str(ending.as_str());
+            }
+            restored.push_str(piece);
+        }
+        self.encoded_to = self.content.len();
+
+        encode_str(
+            self.encoding,
+            &mut self.encoder,
+            &restored,
+            &mut self.out,
+            last,
+        )
+    }
+}
+
+fn encode_str(
+    encoding: &'static Encoding,
+    encoder: &mut encoding_rs::Encoder,
+    text: &str,
+    out: &mut Vec<u8>,
+    last: bool,
+) -> Result<()> {
+    // encoding_rs 按 WHATWG 规范会把 UTF-16 的输出编码换成 UTF-8，只能自己写
+    if encoding == UTF_16LE {
+        out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        return Ok(());
+    }
+    if encoding == UTF_16BE {
+        out.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        return Ok(());
+    }
+
+    let mut text = text;
+    loop {
+        let needed = encoder
+            .max_buffer_length_from_utf8_without_replacement(text.len())
+            .unwrap_or(text.len() * 4 + 16);
+        out.reserve(needed);
+        let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(text, out, last);
+        text = &text[read..];
+        match result {
+            EncoderResult::InputEmpty => return Ok(()),
+            EncoderResult::OutputFull => continue,
+            EncoderResult::Unmappable(character) => {
+                return Err(Unencodable {
+                    encoding,
+                    character,
+                }
+                .into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{GBK, UTF_8};
+
+    use super::*;
+
+    /// 整篇保存时走的那一套
+    fn whole(text: &str, format: &FileFormat, rules: &Rules) -> Option<(Vec<u8>, String)> {
+        let content = rules
+            .apply_content(&line_ending::normalize(text))
+            .into_owned();
+        let restored = line_ending::restore(&content, format.line_ending, &format.line_breaks);
+        let bytes = encoding::encode(&restored, format.encoding, format.bom).ok()?;
+        Some((bytes, content))
+    }
+
+    fn streamed(chunks: &[&[u8]], format: &FileFormat, rules: &Rules) -> Result<Finished> {
+        let mut transcoder = Transcoder::new(format, rules);
+        let mut bytes = Vec::new();
+        for chunk in chunks {
+            transcoder.push(chunk)?;
+            bytes.extend(transcoder.take());
+        }
+        let mut finished = transcoder.finish()?;
+        bytes.append(&mut finished.bytes);
+        finished.bytes = bytes;
+        Ok(finished)
+    }
+
+    fn formats() -> Vec<FileFormat> {
+        let mut formats = Vec::new();
+        for encoding in [UTF_8, GBK, UTF_16LE, UTF_16BE] {
+            for (line_ending, line_breaks) in [
+                (LineEnding::Lf, vec![]),
+                (LineEnding::Crlf, vec![]),
+                (LineEnding::Cr, vec![]),
+                (
+                    LineEnding::Mixed,
+                    vec![LineEnding::Crlf, LineEnding::Lf, LineEnding::Crlf],
+                ),
+            ] {
+                formats.push(FileFormat {
+                    encoding,
+                    bom: encoding != GBK,
+                    line_ending,
+                    line_breaks: line_breaks.into(),
+                });
+            }
+        }
+        formats
+    }
+
+    fn rules() -> Vec<Rules> {
+        let mut rules = Vec::new();
+        for trim in [None, Some(true)] {
+            for final_newline in [None, Some(true), Some(false)] {
+                rules.push(Rules {
+                    trim_trailing_whitespace: trim,
+                    insert_final_newline: final_newline,
+                    ..Rules::default()
+                });
+            }
+        }
+        rules
+    }
+
+    const SAMPLES: &[&str] = &[
+        "",
+        "\n",
+        "\n\n\n",
+        "第一行",
+        "第一行\n第二行\n",
+        "a  \n\tb\t\n  \n",
+        "行尾空格 \r\n中间  空格\r\n\r\n",
+        "cr\rcrlf\r\nlf\nend  ",
+        "trailing \n\n\n",
+        "  \t ",
+        "\r",
+        "😀 emoji\r\n",
+    ];
+
+    #[test]
+    fn same_as_saving_the_whole_text() {
+        for format in formats() {
+            for rules in rules() {
+                for text in SAMPLES {
+                    let input = text.as_bytes();
+                    let Some((bytes, content)) = whole(text, &format, &rules) else {
+                        // 编码不了的字符，整篇保存和流式保存一样报错
+                        assert!(streamed(&[input], &format, &rules).is_err(), "{:?}", text);
+                        continue;
+                    };
+                    // 在每个字节处切成两块，包括切开一个字符、切开 \r\n
+                    for at in 0..=input.len() {
+                        let (head, tail) = input.split_at(at);
+                        let finished = streamed(&[head, tail], &format, &rules).unwrap();
+                        let context = format!("{:?} split at {} with {:?}", text, at, rules);
+                        assert_eq!(finished.bytes, bytes, "{}", context);
+                        assert_eq!(finished.content, content, "{}", context);
+                        assert_eq!(
+                            finished.reformatted,
+                            content != line_ending::normalize(text),
+                            "{}",
+                            context
+                        );
+                    }
+
+                    // 一个字节一块
+                    let bytewise: Vec<&[u8]> = input.chunks(1).collect();
+                    let finished = streamed(&bytewise, &format, &rules).unwrap();
+                    assert_eq!(finished.bytes, bytes, "{:?} byte by byte", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected() {
+        let format = FileFormat::new(UTF_8);
+        let rules = Rules::default();
+
+        let err = streamed(&[b"ok \xff"], &format, &rules).err().unwrap();
+        assert!(err.downcast_ref::<NotUtf8>().is_some());
+        // 最后一个字符没收完
+        let err = streamed(&[b"ok \xe4\xb8"], &format, &rules).err().unwrap();
+        assert!(err.downcast_ref::<NotUtf8>().is_some());
+    }
+
+    #[test]
+    fn unencodable_character() {
+        let format = FileFormat::new(GBK);
+        let err = streamed(&["中文 😀".as_bytes()], &format, &Rules::default())
+            .err()
+            .unwrap();
+        let err = err.downcast_ref::<Unencodable>().unwrap();
+        assert_eq!(err.character, '😀');
+    }
+}
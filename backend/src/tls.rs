@@ -0,0 +1,172 @@
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::io::AsyncWriteExt;
+
+/// 自签名证书缓存的位置，每次启动都用同一份，浏览器只用信任一次
+fn cache_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join("simply-writer").join("tls"))
+        .context("No config directory to keep the certificate in, use --tls-cert and --tls-key")
+}
+
+/// 证书要覆盖的名字：本机的几种叫法、`--tls-name` 给的名字，加上监听地址；
+/// 监听 0.0.0.0 或 :: 时换成本机网卡的地址，局域网里别的机器就是用这些地址访问的
+fn names(hosts: &[IpAddr], extra: &[String]) -> Vec<String> {
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".into(), "::1".into()];
+    let mut add = |name: String| {
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    };
+
+    for name in extra {
+        add(name.trim().to_string());
+    }
+    for ip in hosts.iter().filter(|ip| !ip.is_unspecified()) {
+        add(ip.to_string());
+    }
+    if hosts.iter().any(IpAddr::is_unspecified) {
+        // :: 也收 IPv4 的连接，和 0.0.0.0 一起监听时反正两边都有
+        let v6 = hosts.iter().any(|ip| ip.is_unspecified() && ip.is_ipv6());
+        for ip in interface_addresses() {
+            if ip.is_ipv4() || v6 {
+                add(ip.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// 本机网卡的地址，不含回环和链路本地地址（fe80:: 在浏览器地址栏里还得带网卡编号，用不上）
+fn interface_addresses() -> Vec<IpAddr> {
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .iter()
+            .filter(|interface| !interface.is_loopback() && !interface.is_link_local())
+            .map(|interface| interface.ip())
+            .collect(),
+        Err(e) => {
+            eprintln!(
+                "Warning: Cannot list network interfaces ({}), add the address with --tls-name",
+                e
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// 用户给了证书就用用户的，不然用缓存的自签名证书，没有或者不覆盖现在的地址就重新生成
+pub async fn config(
+    cert: Option<&Path>,
+    key: Option<&Path>,
+    hosts: &[IpAddr],
+    extra_names: &[String],
+) -> Result<RustlsConfig> {
+    if let (Some(cert), Some(key)) = (cert, key) {
+        return RustlsConfig::from_pem_file(cert, key)
+            .await
+            .with_context(|| format!("Failed to load {} and {}", cert.display(), key.display()));
+    }
+
+    let dir = cache_dir()?;
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+    let names_file = dir.join("names.txt");
+
+    let names = names(hosts, extra_names);
+    let cached = tokio::fs::read_to_string(&names_file)
+        .await
+        .unwrap_or_default();
+    let covered = names
+        .iter()
+        .all(|name| cached.lines().any(|line| line == name));
+    if covered && cert.exists() && key.exists() {
+        return RustlsConfig::from_pem_file(&cert, &key)
+            .await
+            .with_context(|| {
+                format!("Failed to load the cached certificate in {}", dir.display())
+            });
+    }
+
+    // 以前缓存过的名字也留着，换回原来的地址不用再信任一次
+    let mut all = names;
+    for line in cached.lines() {
+        if !line.is_empty() && !all.iter().any(|name| name == line) {
+            all.push(line.to_string());
+        }
+    }
+
+    let generated = rcgen::generate_simple_self_signed(all.clone())
+        .context("Failed to generate a certificate")?;
+    let cert_pem = generated.cert.pem();
+    let key_pem = generated.key_pair.serialize_pem();
+
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    tokio::fs::write(&cert, &cert_pem).await?;
+    write_private(&key, key_pem.as_bytes()).await?;
+    tokio::fs::write(&names_file, all.join("\n")).await?;
+    println!("Generated a self-signed certificate in {}", dir.display());
+
+    RustlsConfig::from_pem(cert_pem.into_bytes(), key_pem.into_bytes())
+        .await
+        .context("Failed to load the generated certificate")
+}
+
+/// 私钥只给自己读
+async fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    file.write_all(bytes).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_cover_hosts_and_extra_names() {
+        let hosts: Vec<IpAddr> = vec!["192.168.1.5".parse().unwrap(), "127.0.0.1".parse().unwrap()];
+        let names = names(&hosts, &["mybox.local".into(), " ".into()]);
+        assert_eq!(
+            names,
+            [
+                "localhost",
+                "127.0.0.1",
+                "::1",
+                "mybox.local",
+                "192.168.1.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn unspecified_hosts_become_interface_addresses() {
+        let names = names(&["0.0.0.0".parse().unwrap()], &[]);
+        assert!(!names.iter().any(|name| name == "0.0.0.0"));
+        for ip in interface_addresses().into_iter().filter(IpAddr::is_ipv4) {
+            assert!(names.contains(&ip.to_string()));
+        }
+        // 只监听 IPv4 就不用放 IPv6 的地址
+        assert!(
+            names
+                .iter()
+                .filter_map(|name| name.parse::<IpAddr>().ok())
+                .all(|ip| ip.is_ipv4() || ip.is_loopback())
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{ModifyKind, RenameMode},
+};
+use tokio::sync::mpsc;
+
+/// 编辑器保存时往往是一连串事件（写临时文件、rename、改属性），攒一会儿再报
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Modified,
+    Removed,
+    /// 被改名走了，知道新名字的话带上
+    Renamed(Option<PathBuf>),
+}
+
+/// 一批事件里看到的东西
+#[derive(Default)]
+struct Pending {
+    relevant: bool,
+    renamed_to: Option<Option<PathBuf>>,
+}
+
+impl Pending {
+    fn consider(&mut self, event: Event, name: &OsString) {
+        let is_ours = |p: &PathBuf| p.file_name() == Some(name.as_os_str());
+
+        match event.kind {
+            // 自己读文件也会触发，没用
+            EventKind::Access(_) => {}
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                if is_ours(&event.paths[0]) {
+                    self.relevant = true;
+                    self.renamed_to = Some(Some(event.paths[1].clone()));
+                } else if is_ours(&event.paths[1]) {
+                    self.relevant = true;
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From))
+                if event.paths.iter().any(is_ours) =>
+            {
+                self.relevant = true;
+                self.renamed_to.get_or_insert(None);
+            }
+            _ => self.relevant |= event.paths.iter().any(is_ours),
+        }
+    }
+}
+
+/// 监听文件所在的目录（文件被替换 / 删掉再建时监听文件本身会断），只报这个文件的变化
+///
+/// 返回的 watcher 被 drop 之后就不再有事件
+pub fn watch(path: &Path) -> Result<(RecommendedWatcher, mpsc::UnboundedReceiver<Change>)> {
+    let path = std::path::absolute(path)?;
+    let dir = path
+        .parent()
+        .with_context(|| format!("{} has no parent directory", path.display()))?
+        .to_path_buf();
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?
+        .to_os_string();
+
+    let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            let _ = raw_tx.send(event);
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = raw_rx.recv().await {
+            let mut pending = Pending::default();
+            pending.consider(event, &name);
+            if !pending.relevant {
+                continue;
+            }
+
+            let deadline = tokio::time::sleep(DEBOUNCE);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    _ = &mut deadline => break,
+                    event = raw_rx.recv() => match event {
+                        Some(event) => pending.consider(event, &name),
+                        None => break,
+                    },
+                }
+            }
+
+            // 最后以文件现在在不在为准：改名走又有新文件 rename 过来就是被修改了
+            let change = if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                Change::Modified
+            } else if let Some(to) = pending.renamed_to {
+                Change::Renamed(to)
+            } else {
+                Change::Removed
+            };
+
+            if tx.send(change).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((watcher, rx))
+}
@@ -0,0 +1,27 @@
+// 后端每次启动随机生成一个令牌，跟在打印出来的地址后面（?token=...）
+// 记下来以后从地址栏去掉，刷新页面、再开标签页都不用重新带
+const TOKEN_KEY = "simply-writer-token";
+const TOKEN_HEADER = "X-Simply-Writer-Token";
+
+const readToken = () => {
+  const url = new URL(location.href);
+  const token = url.searchParams.get("token");
+  if (token) {
+    localStorage.setItem(TOKEN_KEY, token);
+    url.searchParams.delete("token");
+    history.replaceState(history.state, "", url);
+  }
+  return token ?? localStorage.getItem(TOKEN_KEY) ?? "";
+};
+
+const token = readToken();
+
+export const apiFetch = (url: string, init: RequestInit = {}) => {
+  const headers = new Headers(init.headers);
+  headers.set(TOKEN_HEADER, token);
+  return fetch(url, { ...init, headers });
+};
+
+// EventSource 设不了请求头，只能放在查询参数里
+export const withToken = (url: string) =>
+  `${url}${url.includes("?") ? "&" : "?"}${new URLSearchParams({ token })}`;
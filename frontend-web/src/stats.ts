@@ -0,0 +1,171 @@
+// 字数统计，和后端 backend/src/stats.rs 一模一样的口径（Word 的“字数统计”），
+// 改的时候两边一起改。打字时在前端数，不用把整篇文章发给后端
+
+// 中文大约每分钟读 400 字
+const CJK_PER_MINUTE = 400;
+// 英文大约每分钟读 200 词
+const WORDS_PER_MINUTE = 200;
+
+export type Stats = {
+  words: number;
+  characters: number;
+  characters_no_spaces: number;
+  characters_no_punctuation: number;
+  cjk_characters: number;
+  latin_words: number;
+  paragraphs: number;
+  lines: number;
+  reading_minutes: number;
+};
+
+// space 空白；cjk 汉字、假名，一个字算一个；hangul 韩文按词算；
+// wide 中文标点和全角字符，一个算一个；
+// ambiguous 弯引号、破折号、省略号这些中英文都用的，挨着英文就算进单词里
+type Class = "space" | "cjk" | "hangul" | "wide" | "ambiguous" | "latin";
+
+const inRange = (code: number, ranges: [number, number][]) =>
+  ranges.some(([from, to]) => code >= from && code <= to);
+
+// 和 Rust 的 char::is_whitespace 一样：JS 的 \s 多了 U+FEFF，少了 U+0085
+const isSpace = (c: string) => c === "\u0085" || (c !== "\ufeff" && /\s/.test(c));
+
+const HANGUL: [number, number][] = [
+  [0x1100, 0x11ff],
+  [0x3130, 0x318f],
+  [0xac00, 0xd7af],
+];
+const CJK: [number, number][] = [
+  [0x3005, 0x3007],
+  [0x3040, 0x30ff],
+  [0x3100, 0x312f],
+  [0x31a0, 0x31ff],
+  [0x3400, 0x4dbf],
+  [0x4e00, 0x9fff],
+  [0xf900, 0xfaff],
+  [0xff66, 0xff9f],
+  [0x20000, 0x323af],
+];
+const WIDE: [number, number][] = [
+  [0x3000, 0x303f],
+  [0xfe10, 0xfe1f],
+  [0xfe30, 0xfe6f],
+  [0xff01, 0xff65],
+  [0xffe0, 0xffee],
+];
+const PUNCTUATION: [number, number][] = [
+  [0x2010, 0x205e],
+  [0x3001, 0x3003],
+  [0x3008, 0x3011],
+  [0x3014, 0x301f],
+  [0x30fb, 0x30fb],
+  [0xfe10, 0xfe1f],
+  [0xfe30, 0xfe6f],
+  [0xff01, 0xff0f],
+  [0xff1a, 0xff20],
+  [0xff3b, 0xff40],
+  [0xff5b, 0xff65],
+];
+
+const classify = (c: string): Class => {
+  if (isSpace(c)) return "space";
+  const code = c.codePointAt(0)!;
+  if (inRange(code, HANGUL)) return "hangul";
+  if (code === 0x30fb) return "wide";
+  if (inRange(code, CJK)) return "cjk";
+  if (inRange(code, WIDE)) return "wide";
+  if (code >= 0x2010 && code <= 0x205e) return "ambiguous";
+  return "latin";
+};
+
+const isPunctuation = (c: string) =>
+  /^[!-/:-@[-`{-~]$/.test(c) ||
+  "¡§«¶·»¿".includes(c) ||
+  inRange(c.codePointAt(0)!, PUNCTUATION);
+
+// 内容里的换行是 \n
+export const countStats = (content: string): Stats => {
+  const stats: Stats = {
+    words: 0,
+    characters: 0,
+    characters_no_spaces: 0,
+    characters_no_punctuation: 0,
+    cjk_characters: 0,
+    latin_words: 0,
+    paragraphs: 0,
+    lines: 0,
+    reading_minutes: 0,
+  };
+  // 正在数的英文 / 韩文单词
+  let run: Class | null = null;
+  let last: Class = "space";
+  const chars = Array.from(content);
+
+  for (const [i, c] of chars.entries()) {
+    if (c === "\n") {
+      run = null;
+      last = "space";
+      continue;
+    }
+
+    stats.characters += 1;
+    const cls = classify(c);
+    if (cls !== "space") {
+      stats.characters_no_spaces += 1;
+      if (!isPunctuation(c)) stats.characters_no_punctuation += 1;
+    }
+
+    switch (cls) {
+      case "space":
+        run = null;
+        break;
+      case "cjk":
+      case "wide":
+        run = null;
+        stats.words += 1;
+        if (cls === "cjk") stats.cjk_characters += 1;
+        break;
+      case "ambiguous":
+        if (run === null) {
+          // 前面不是中文、后面紧跟着英文，比如 “Hello”，就是英文单词的开头
+          const next = chars[i + 1];
+          if (
+            last !== "cjk" &&
+            last !== "wide" &&
+            next !== undefined &&
+            classify(next) === "latin"
+          ) {
+            run = "latin";
+            stats.latin_words += 1;
+          }
+          stats.words += 1;
+        }
+        break;
+      case "hangul":
+      case "latin":
+        if (cls === "hangul") stats.cjk_characters += 1;
+        if (run !== cls) {
+          run = cls;
+          stats.words += 1;
+          if (cls === "latin") stats.latin_words += 1;
+        }
+        break;
+    }
+    last = cls;
+  }
+
+  const lines = content.split("\n");
+  if (content !== "") {
+    // 结尾的换行后面不算一行
+    stats.lines = content.endsWith("\n") ? lines.length - 1 : lines.length;
+  }
+  stats.paragraphs = lines.filter((line) =>
+    Array.from(line).some((c) => !isSpace(c)),
+  ).length;
+  stats.reading_minutes = Math.ceil(
+    (stats.cjk_characters * WORDS_PER_MINUTE +
+      stats.latin_words * CJK_PER_MINUTE) /
+      (CJK_PER_MINUTE * WORDS_PER_MINUTE),
+  );
+
+  return stats;
+};
@@ -10,6 +10,10 @@ export default defineConfig({
       '/api': {
         target: 'http://localhost:3000',
         changeOrigin: true,
+        // 后端只认同源的 Origin，开发服务器在另一个端口上
+        configure: (proxy) => {
+          proxy.on('proxyReq', (req) => req.removeHeader('origin'))
+        },
       }
     }
   }